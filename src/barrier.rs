@@ -0,0 +1,57 @@
+#[cfg(any(
+    target_arch = "arm",
+    target_arch = "aarch64",
+    target_arch = "riscv32",
+    target_arch = "riscv64",
+    target_arch = "loongarch64"
+))]
+use core::arch::asm;
+use core::sync::atomic::{compiler_fence, Ordering};
+
+// `compiler_fence` alone is enough on x86, which orders memory accesses
+// against port and MMIO accesses, but not on weakly ordered CPUs.
+
+/// Make prior writes to memory visible to the device
+/// before the following write to one of its registers.
+pub(crate) fn dma_wmb() {
+    compiler_fence(Ordering::Release);
+    #[cfg(target_arch = "arm")]
+    unsafe {
+        asm!("dmb oshst", options(nostack, preserves_flags));
+    }
+    #[cfg(target_arch = "aarch64")]
+    unsafe {
+        asm!("dmb oshst", options(nostack, preserves_flags));
+    }
+    #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+    unsafe {
+        asm!("fence w,o", options(nostack, preserves_flags));
+    }
+    #[cfg(target_arch = "loongarch64")]
+    unsafe {
+        asm!("dbar 0", options(nostack, preserves_flags));
+    }
+}
+
+/// Make following reads of memory written by the device
+/// happen after the prior read that found the device done.
+pub(crate) fn dma_rmb() {
+    // ARMv7 has no load-only barrier
+    #[cfg(target_arch = "arm")]
+    unsafe {
+        asm!("dmb osh", options(nostack, preserves_flags));
+    }
+    #[cfg(target_arch = "aarch64")]
+    unsafe {
+        asm!("dmb oshld", options(nostack, preserves_flags));
+    }
+    #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+    unsafe {
+        asm!("fence r,r", options(nostack, preserves_flags));
+    }
+    #[cfg(target_arch = "loongarch64")]
+    unsafe {
+        asm!("dbar 0", options(nostack, preserves_flags));
+    }
+    compiler_fence(Ordering::Acquire);
+}
@@ -0,0 +1,174 @@
+use crate::dma::FwCfgDmaAccess;
+use crate::{Error, FwCfg, FwCfgFile};
+use core::marker::PhantomData;
+
+/// A batch of up to `N` DMA operations executed back to back
+/// by [`FwCfg::run_dma_batch`].
+///
+/// Each operation takes a single DMA descriptor, with selecting an item
+/// merged into the operation where possible.
+///
+/// # Examples
+/// ```
+/// use qemu_fw_cfg::{DmaBatch, FwCfg};
+///
+/// let mut fw_cfg = unsafe { FwCfg::take().unwrap() };
+/// let rsdp = fw_cfg.find_file("etc/acpi/rsdp").unwrap().unwrap();
+/// let tables = fw_cfg.find_file("etc/acpi/tables").unwrap().unwrap();
+/// let mut rsdp_data = [0u8; 36];
+/// let mut tables_data = [0u8; 4096];
+///
+/// let mut batch = DmaBatch::<2>::new();
+/// batch.read_file(&rsdp, &mut rsdp_data);
+/// batch.read_file(&tables, &mut tables_data);
+/// let [rsdp_result, tables_result] = fw_cfg.run_dma_batch(batch);
+/// ```
+#[derive(Debug)]
+pub struct DmaBatch<'a, const N: usize> {
+    operations: [Option<Operation>; N],
+    len: usize,
+    _buffers: PhantomData<&'a mut [u8]>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Operation {
+    control: u32,
+    data: *mut u8,
+    len: usize,
+}
+
+impl<'a, const N: usize> DmaBatch<'a, N> {
+    /// Create an empty batch.
+    pub fn new() -> Self {
+        Self {
+            operations: [None; N],
+            len: 0,
+            _buffers: PhantomData,
+        }
+    }
+
+    /// The number of operations in this batch.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Return `true` if this batch has no operations.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Select a file and read its data into `buffer`, from the start.
+    ///
+    /// This fills up to `buffer.len()` bytes, and a shorter buffer is not an error.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the batch is full.
+    pub fn read_file(&mut self, file: &FwCfgFile, buffer: &'a mut [u8]) -> &mut Self {
+        let len = file.size().min(buffer.len());
+        let control = FwCfgDmaAccess::select(file.key()) | FwCfgDmaAccess::READ;
+        self.push(control, buffer.as_mut_ptr(), len)
+    }
+
+    /// Select a file and write `data` into it, from the start.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the batch is full.
+    pub fn write_file(&mut self, file: &FwCfgFile, data: &'a [u8]) -> &mut Self {
+        let control = FwCfgDmaAccess::select(file.key()) | FwCfgDmaAccess::WRITE;
+        self.push(control, data.as_ptr() as *mut u8, data.len())
+    }
+
+    /// Select a file without transferring any data.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the batch is full.
+    pub fn select(&mut self, file: &FwCfgFile) -> &mut Self {
+        let control = FwCfgDmaAccess::select(file.key());
+        self.push(control, core::ptr::null_mut(), 0)
+    }
+
+    /// Skip `len` bytes of the selected item.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the batch is full.
+    pub fn skip(&mut self, len: usize) -> &mut Self {
+        self.push(FwCfgDmaAccess::SKIP, core::ptr::null_mut(), len)
+    }
+
+    /// Read the selected item into `buffer`, continuing where the previous
+    /// operation stopped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the batch is full.
+    pub fn read(&mut self, buffer: &'a mut [u8]) -> &mut Self {
+        self.push(FwCfgDmaAccess::READ, buffer.as_mut_ptr(), buffer.len())
+    }
+
+    /// Write `data` into the selected item, continuing where the previous
+    /// operation stopped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the batch is full.
+    pub fn write(&mut self, data: &'a [u8]) -> &mut Self {
+        self.push(FwCfgDmaAccess::WRITE, data.as_ptr() as *mut u8, data.len())
+    }
+
+    fn push(&mut self, control: u32, data: *mut u8, len: usize) -> &mut Self {
+        let slot = self
+            .operations
+            .get_mut(self.len)
+            .expect("DMA batch is full");
+        *slot = Some(Operation { control, data, len });
+        self.len += 1;
+        self
+    }
+}
+
+impl<const N: usize> Default for DmaBatch<'_, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FwCfg {
+    /// Execute a batch of DMA operations in order, returning the result of
+    /// each operation at the index it was added at.
+    ///
+    /// After an operation fails, the following operations are not executed
+    /// and their result is `None` until one of them selects another item.
+    pub fn run_dma_batch<const N: usize>(
+        &mut self,
+        batch: DmaBatch<'_, N>,
+    ) -> [Option<Result<(), Error>>; N] {
+        let mut results = [(); N].map(|_| None);
+        let mut failed = false;
+        let mut address_reset = false;
+
+        for (operation, result) in batch.operations.iter().zip(results.iter_mut()) {
+            let operation = match operation {
+                Some(operation) => operation,
+                None => break,
+            };
+            if failed && (operation.control & FwCfgDmaAccess::SELECT) == 0 {
+                continue;
+            }
+            let ret = self.dma_after(
+                operation.control,
+                operation.data,
+                operation.len,
+                address_reset,
+            );
+            failed = ret.is_err();
+            address_reset = ret.is_ok();
+            *result = Some(ret);
+        }
+
+        results
+    }
+}
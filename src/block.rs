@@ -0,0 +1,161 @@
+use core::cell::RefCell;
+
+use crate::{Error, FwCfg, FwCfgFile, FwCfgReader};
+
+/// A read-only block device over the data of a file,
+/// such as a disk image passed with `-fw_cfg`.
+///
+/// Blocks are `BLOCK_SIZE` bytes, and the last block is padded with zeros if
+/// the file size is not a multiple of it. Blocks are read at any offset
+/// using [`FwCfgReader`].
+///
+/// Only the last block read with [`FwCfgBlockDevice::read_block`] is cached,
+/// which avoids reading it again when a filesystem goes back and forth
+/// between a few blocks of metadata and the data. Filesystems reading many
+/// blocks repeatedly should keep their own cache.
+///
+/// With the `embedded-sdmmc` feature, 512-byte block devices implement
+/// `embedded_sdmmc::BlockDevice`, whose writes fail with
+/// [`Error::ReadOnly`]. Filesystem crates reading bytes instead of blocks
+/// can use [`FwCfgReader`] with the `embedded-io` feature.
+///
+/// # Examples
+/// ```
+/// use qemu_fw_cfg::{FwCfg, FwCfgBlockDevice};
+///
+/// let mut fw_cfg = unsafe { FwCfg::take().unwrap() };
+/// let file = fw_cfg.find_file("opt/com.example/rootfs.img").unwrap().unwrap();
+/// let mut device = FwCfgBlockDevice::<512>::new(&mut fw_cfg, &file);
+/// let boot_sector = device.read_block(0).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct FwCfgBlockDevice<'a, const BLOCK_SIZE: usize = 512> {
+    // `embedded_sdmmc::BlockDevice` reads through a shared reference.
+    inner: RefCell<Inner<'a, BLOCK_SIZE>>,
+}
+
+#[derive(Debug)]
+struct Inner<'a, const BLOCK_SIZE: usize> {
+    reader: FwCfgReader<'a>,
+    cache: [u8; BLOCK_SIZE],
+    cached: Option<u64>,
+}
+
+impl<'a, const BLOCK_SIZE: usize> FwCfgBlockDevice<'a, BLOCK_SIZE> {
+    /// Build a block device over `file`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `BLOCK_SIZE` is zero.
+    pub fn new(fw_cfg: &'a mut FwCfg, file: &FwCfgFile) -> Self {
+        assert!(BLOCK_SIZE > 0, "block size is zero");
+        Self {
+            inner: RefCell::new(Inner {
+                reader: fw_cfg.reader(file),
+                cache: [0; BLOCK_SIZE],
+                cached: None,
+            }),
+        }
+    }
+
+    /// The size of a block in bytes.
+    pub fn block_size(&self) -> usize {
+        BLOCK_SIZE
+    }
+
+    /// The number of blocks, including a partial last block.
+    pub fn num_blocks(&self) -> u64 {
+        self.inner.borrow().num_blocks()
+    }
+
+    /// Read the block at `index`, or return it from the cache.
+    ///
+    /// Returns [`Error::OutOfRange`] if the block is past the end.
+    pub fn read_block(&mut self, index: u64) -> Result<&[u8; BLOCK_SIZE], Error> {
+        self.inner.get_mut().read_block(index)
+    }
+
+    /// Read consecutive blocks starting at `start` into `buffer`,
+    /// whose length must be a multiple of the block size.
+    ///
+    /// Returns [`Error::OutOfRange`] if any block is past the end.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the length of `buffer` is not a multiple of the block size.
+    pub fn read_blocks(&mut self, start: u64, buffer: &mut [u8]) -> Result<(), Error> {
+        self.inner.get_mut().read_blocks(start, buffer)
+    }
+}
+
+impl<const BLOCK_SIZE: usize> Inner<'_, BLOCK_SIZE> {
+    fn num_blocks(&self) -> u64 {
+        (self.reader.size() as u64).div_ceil(BLOCK_SIZE as u64)
+    }
+
+    fn read_block(&mut self, index: u64) -> Result<&[u8; BLOCK_SIZE], Error> {
+        if self.cached != Some(index) {
+            self.cached = None;
+            let mut cache = [0; BLOCK_SIZE];
+            self.read_blocks(index, &mut cache)?;
+            self.cache = cache;
+            self.cached = Some(index);
+        }
+        Ok(&self.cache)
+    }
+
+    fn read_blocks(&mut self, start: u64, buffer: &mut [u8]) -> Result<(), Error> {
+        assert!(
+            buffer.len() % BLOCK_SIZE == 0,
+            "buffer is not a multiple of the block size"
+        );
+        let count = (buffer.len() / BLOCK_SIZE) as u64;
+        let end = start.checked_add(count).ok_or(Error::OutOfRange)?;
+        if end > self.num_blocks() {
+            return Err(Error::OutOfRange);
+        }
+        if count == 1 && self.cached == Some(start) {
+            buffer.copy_from_slice(&self.cache);
+            return Ok(());
+        }
+        // The offset fits since the block is within the file.
+        self.reader.seek((start * BLOCK_SIZE as u64) as usize);
+        let len = self.reader.read(buffer)?;
+        buffer[len..].fill(0);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "embedded-sdmmc")]
+impl embedded_sdmmc::BlockDevice for FwCfgBlockDevice<'_, 512> {
+    type Error = Error;
+
+    fn read(
+        &self,
+        blocks: &mut [embedded_sdmmc::Block],
+        start_block_idx: embedded_sdmmc::BlockIdx,
+        _reason: &str,
+    ) -> Result<(), Error> {
+        let mut inner = self.inner.borrow_mut();
+        for (index, block) in (u64::from(start_block_idx.0)..).zip(blocks) {
+            inner.read_blocks(index, &mut block.contents)?;
+        }
+        Ok(())
+    }
+
+    fn write(
+        &self,
+        _blocks: &[embedded_sdmmc::Block],
+        _start_block_idx: embedded_sdmmc::BlockIdx,
+    ) -> Result<(), Error> {
+        Err(Error::ReadOnly)
+    }
+
+    fn num_blocks(&self) -> Result<embedded_sdmmc::BlockCount, Error> {
+        use core::convert::TryFrom;
+
+        let count =
+            u32::try_from(FwCfgBlockDevice::num_blocks(self)).map_err(|_| Error::OutOfRange)?;
+        Ok(embedded_sdmmc::BlockCount(count))
+    }
+}
@@ -0,0 +1,66 @@
+use crate::mmio::MemoryMappedDevice;
+use crate::{Error, FwCfg, FwCfgQuirks, MmioLayout, Mode};
+
+/// A builder for [`FwCfg`] with non-default quirks or register layout.
+///
+/// # Examples
+/// ```no_run
+/// use qemu_fw_cfg::{FwCfgBuilder, FwCfgQuirks, MmioLayout};
+///
+/// let fw_cfg = unsafe {
+///     FwCfgBuilder::new()
+///         .quirks(FwCfgQuirks::GENERIC)
+///         .layout(MmioLayout::MAC99)
+///         .take_memory_mapped(0xf000_0510 as *mut ())
+/// };
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FwCfgBuilder {
+    quirks: FwCfgQuirks,
+    layout: MmioLayout,
+}
+
+impl FwCfgBuilder {
+    /// Create a builder for QEMU with the `virt` register layout.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Build for a device with the given `quirks`.
+    pub fn quirks(mut self, quirks: FwCfgQuirks) -> Self {
+        self.quirks = quirks;
+        self
+    }
+
+    /// Build for a memory-mapped device with the given register `layout`.
+    ///
+    /// This is ignored for the x86/x86-64 I/O port.
+    pub fn layout(mut self, layout: MmioLayout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Take the fw_cfg device at the x86/x86-64 I/O port, like [`FwCfg::take`].
+    ///
+    /// # Safety
+    ///
+    /// See [`FwCfg::take`].
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    pub unsafe fn take_x86(self) -> Result<FwCfg, Error> {
+        FwCfg::take_with(|| FwCfg::new_for_mode(Mode::IOPort, self.quirks))
+    }
+
+    /// Take the fw_cfg device memory-mapped at the given base pointer,
+    /// like [`FwCfg::take_memory_mapped`].
+    ///
+    /// Returns [`Error::InvalidLayout`] if the layout is not supported,
+    /// see [`MmioLayout`].
+    ///
+    /// # Safety
+    ///
+    /// See [`FwCfg::take_memory_mapped`]. The layout must describe the device.
+    pub unsafe fn take_memory_mapped(self, base_ptr: *mut ()) -> Result<FwCfg, Error> {
+        let device = MemoryMappedDevice::new(base_ptr, self.layout)?;
+        FwCfg::take_with(|| FwCfg::new_for_mode(Mode::MemoryMapped(device), self.quirks))
+    }
+}
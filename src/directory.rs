@@ -0,0 +1,283 @@
+#[cfg(doc)]
+use crate::FwCfgQuirks;
+use crate::{Error, FwCfg, FwCfgFile};
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+/// An index of the fw_cfg directory, read from the device once.
+///
+/// Entries are stored in `S`, which is either an array with a fixed capacity,
+/// a `Vec` with the `alloc` feature, or any other storage given to
+/// [`FwCfgDirectory::read_into`]. Lookups use binary search when the
+/// directory is sorted by name, as QEMU does. Entries are sorted after
+/// reading them for devices that do not, see [`FwCfgQuirks::sorted_directory`].
+///
+/// # Examples
+/// ```no_run
+/// use qemu_fw_cfg::{FwCfg, FwCfgDirectory, FwCfgFile};
+///
+/// let mut fw_cfg = unsafe { FwCfg::take().unwrap() };
+/// let directory = FwCfgDirectory::<[FwCfgFile; 64]>::read_array(&mut fw_cfg).unwrap();
+/// let file = directory.find_file("etc/igd-opregion").unwrap();
+/// let data = fw_cfg.read_file(file).unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct FwCfgDirectory<S> {
+    storage: S,
+    len: usize,
+    sorted: bool,
+}
+
+impl<const N: usize> FwCfgDirectory<[FwCfgFile; N]> {
+    /// Read the directory into an array of `N` entries.
+    ///
+    /// Returns [`Error::TooManyEntries`] if the directory has more entries.
+    pub fn read_array(fw_cfg: &mut FwCfg) -> Result<Self, Error> {
+        Self::read_into(fw_cfg, [(); N].map(|_| FwCfgFile::default()))
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl FwCfgDirectory<Vec<FwCfgFile>> {
+    /// Read the entire directory into a `Vec`.
+    pub fn read_vec(fw_cfg: &mut FwCfg) -> Result<Self, Error> {
+        let mut files = fw_cfg.iter_files().collect::<Result<Vec<_>, _>>()?;
+        if !fw_cfg.quirks().sorted_directory {
+            sort(&mut files);
+        }
+        Ok(Self::new(files))
+    }
+}
+
+impl<S: AsRef<[FwCfgFile]>> FwCfgDirectory<S> {
+    /// Read the directory into `storage`, using as many entries as it holds.
+    ///
+    /// Returns [`Error::TooManyEntries`] if the directory has more entries.
+    pub fn read_into(fw_cfg: &mut FwCfg, mut storage: S) -> Result<Self, Error>
+    where
+        S: AsMut<[FwCfgFile]>,
+    {
+        let mut len = 0;
+        for file in fw_cfg.iter_files() {
+            let slot = storage.as_mut().get_mut(len).ok_or(Error::TooManyEntries)?;
+            *slot = file?;
+            len += 1;
+        }
+        if !fw_cfg.quirks().sorted_directory {
+            sort(&mut storage.as_mut()[..len]);
+        }
+        Ok(Self::with_len(storage, len))
+    }
+
+    #[cfg(feature = "alloc")]
+    fn new(storage: S) -> Self {
+        let len = storage.as_ref().len();
+        Self::with_len(storage, len)
+    }
+
+    fn with_len(storage: S, len: usize) -> Self {
+        let files = &storage.as_ref()[..len];
+        let sorted = files
+            .windows(2)
+            .all(|pair| pair[0].name_bytes() < pair[1].name_bytes());
+        Self {
+            storage,
+            len,
+            sorted,
+        }
+    }
+
+    /// All files in the directory.
+    pub fn files(&self) -> &[FwCfgFile] {
+        &self.storage.as_ref()[..self.len]
+    }
+
+    /// The number of files in the directory.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Return `true` if the directory has no files.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Return `true` if the files are sorted by name without duplicates,
+    /// which allows lookups using binary search.
+    pub fn is_sorted(&self) -> bool {
+        self.sorted
+    }
+
+    /// Find a single file by its name. Returns `None` if the file is missing.
+    pub fn find_file(&self, name: &str) -> Option<&FwCfgFile> {
+        let name = name.as_bytes();
+        let files = self.files();
+        if self.sorted {
+            let index = files
+                .binary_search_by(|file| file.name_bytes().cmp(name))
+                .ok()?;
+            Some(&files[index])
+        } else {
+            files.iter().find(|file| file.name_bytes() == name)
+        }
+    }
+
+    /// Find one or more files by their name.
+    ///
+    /// This works the same as [`FwCfg::find_files`].
+    pub fn find_files(&self, entries: &mut [(&str, Option<FwCfgFile>)]) {
+        for (name, ret) in entries.iter_mut() {
+            if let Some(file) = self.find_file(name) {
+                *ret = Some(file.clone());
+            }
+        }
+    }
+
+    /// Return an iterator of the files whose name starts with `prefix`.
+    pub fn iter_files_with_prefix<'a>(
+        &'a self,
+        prefix: &'a str,
+    ) -> impl Iterator<Item = &'a FwCfgFile> {
+        let prefix = prefix.as_bytes();
+        let files = self.files();
+        let sorted = self.sorted;
+        let start = if sorted {
+            files.partition_point(|file| file.name_bytes() < prefix)
+        } else {
+            0
+        };
+        // When sorted, the matching files are next to each other.
+        files[start..]
+            .iter()
+            .take_while(move |file| !sorted || file.name_bytes().starts_with(prefix))
+            .filter(move |file| file.name_bytes().starts_with(prefix))
+    }
+}
+
+impl<S: AsRef<[FwCfgFile]>> FwCfgDirectory<S> {
+    /// Return an iterator of the files whose name matches the glob `pattern`.
+    ///
+    /// See [`FwCfg::iter_files_matching`] for the supported syntax.
+    pub fn iter_files_matching<'a>(
+        &'a self,
+        pattern: &'a str,
+    ) -> impl Iterator<Item = &'a FwCfgFile> {
+        self.files()
+            .iter()
+            .filter(move |file| glob_match(pattern.as_bytes(), file.name_bytes()))
+    }
+
+    /// Return an iterator of the entries directly inside the directory `path`,
+    /// treating `/` in file names as a path separator.
+    ///
+    /// An empty `path` lists the top level. Each subdirectory is returned once.
+    /// Files whose name is not valid UTF-8 are skipped.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use qemu_fw_cfg::{FwCfg, FwCfgDirEntry, FwCfgDirectory, FwCfgFile};
+    ///
+    /// let mut fw_cfg = unsafe { FwCfg::take().unwrap() };
+    /// let directory = FwCfgDirectory::<[FwCfgFile; 64]>::read_array(&mut fw_cfg).unwrap();
+    /// let (mut files, mut dirs) = (0, 0);
+    /// for entry in directory.read_dir("etc") {
+    ///     match entry {
+    ///         FwCfgDirEntry::File(_) => files += 1,
+    ///         FwCfgDirEntry::Dir(_) => dirs += 1,
+    ///     }
+    /// }
+    /// ```
+    pub fn read_dir<'a>(&'a self, path: &'a str) -> impl Iterator<Item = FwCfgDirEntry<'a>> {
+        let path = path.trim_end_matches('/');
+        let files = self.files();
+        let sorted = self.sorted;
+
+        files.iter().enumerate().filter_map(move |(i, file)| {
+            let name = file.try_name().ok()?;
+            let rest = match path {
+                "" => name,
+                path => name.strip_prefix(path)?.strip_prefix('/')?,
+            };
+            let end = match rest.find('/') {
+                Some(end) => name.len() - rest.len() + end,
+                None => return Some(FwCfgDirEntry::File(file)),
+            };
+            let dir = &name[..end];
+
+            // Only return a subdirectory at its first file.
+            // When sorted, the files inside it are next to each other.
+            let earlier = if sorted {
+                &files[i.saturating_sub(1)..i]
+            } else {
+                &files[..i]
+            };
+            let in_dir = |file: &FwCfgFile| {
+                let name = file.name_bytes();
+                name.starts_with(dir.as_bytes()) && name.get(dir.len()) == Some(&b'/')
+            };
+            if earlier.iter().any(in_dir) {
+                None
+            } else {
+                Some(FwCfgDirEntry::Dir(dir))
+            }
+        })
+    }
+}
+
+/// An entry returned by [`FwCfgDirectory::read_dir`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FwCfgDirEntry<'a> {
+    /// A file directly inside the directory
+    File(&'a FwCfgFile),
+    /// A subdirectory, given as its full path without a trailing `/`
+    Dir(&'a str),
+}
+
+/// Sort `files` by name, for devices that do not sort the directory.
+fn sort(files: &mut [FwCfgFile]) {
+    files.sort_unstable_by(|a, b| a.name_bytes().cmp(b.name_bytes()));
+}
+
+/// Match `name` against the glob `pattern`, segment by segment.
+pub(crate) fn glob_match(pattern: &[u8], name: &[u8]) -> bool {
+    let mut patterns = pattern.split(|&b| b == b'/');
+    let mut names = name.split(|&b| b == b'/');
+    loop {
+        match (patterns.next(), names.next()) {
+            (Some(pattern), Some(name)) if glob_match_segment(pattern, name) => {}
+            (None, None) => return true,
+            _ => return false,
+        }
+    }
+}
+
+/// Match a path segment, where `*` matches any number of bytes and `?` matches one.
+fn glob_match_segment(pattern: &[u8], name: &[u8]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // The position of the last `*` and where it started matching from
+    let mut star = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&b) if b == b'?' || b == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                // Let the last `*` match one more byte and try again
+                Some((star_p, star_n)) => {
+                    star = Some((star_p, star_n + 1));
+                    p = star_p + 1;
+                    n = star_n + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&b| b == b'*')
+}
@@ -0,0 +1,662 @@
+use crate::barrier::{dma_rmb, dma_wmb};
+use crate::{Error, FwCfg, FwCfgFeatures, FwCfgFile, FwCfgKey, Mode};
+use core::cell::UnsafeCell;
+use core::convert::TryFrom;
+use core::fmt;
+use core::hint::spin_loop;
+use core::mem::{align_of, size_of, MaybeUninit};
+use core::ptr;
+
+/// A region of memory shared with the host, used to stage DMA transfers.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct DmaBuffer {
+    ptr: *mut u8,
+    len: usize,
+}
+
+const BOUNCE_BUFFER_SIZE: usize = 4096;
+
+/// Memory owned by this crate for staging DMA operations that may time out
+/// when no DMA buffer is registered, see [`FwCfg::set_dma_poll`].
+static BOUNCE_BUFFER: BounceBuffer = BounceBuffer(UnsafeCell::new([0; BOUNCE_BUFFER_SIZE]));
+
+// Aligned to cache lines for `DmaCache`.
+#[repr(C, align(64))]
+struct BounceBuffer(UnsafeCell<[u8; BOUNCE_BUFFER_SIZE]>);
+
+// SAFETY: Only the one `FwCfg` value that may exist at the same time
+// accesses it, see `FwCfg::take`.
+unsafe impl Sync for BounceBuffer {}
+
+/// A translator from virtual addresses to the physical addresses used by the device.
+///
+/// Without a translator, memory is assumed to be identity-mapped.
+///
+/// This is implemented for closures, for example:
+/// ```
+/// use qemu_fw_cfg::FwCfg;
+///
+/// const KERNEL_BASE: usize = 0xffff_8000_0000_0000;
+///
+/// let mut fw_cfg = unsafe { FwCfg::take().unwrap() };
+/// fw_cfg.set_virt_to_phys(&|virt: usize| Some(virt.checked_sub(KERNEL_BASE)? as u64));
+/// ```
+pub trait VirtToPhys {
+    /// Return the physical address mapped at `virt`, or `None` if it is not mapped.
+    fn virt_to_phys(&self, virt: usize) -> Option<u64>;
+
+    /// The granularity of the mapping.
+    ///
+    /// Buffers spanning more than one page are checked to be
+    /// physically contiguous page by page.
+    fn page_size(&self) -> usize {
+        4096
+    }
+
+    /// Return the virtual address where `phys` is mapped,
+    /// or `None` if it is not mapped.
+    ///
+    /// This is only used by [`FwCfg::read_file_to_phys`] when it cannot use DMA.
+    /// The default implementation maps nothing.
+    fn phys_to_virt(&self, phys: u64) -> Option<usize> {
+        let _ = phys;
+        None
+    }
+}
+
+impl<F: Fn(usize) -> Option<u64>> VirtToPhys for F {
+    fn virt_to_phys(&self, virt: usize) -> Option<u64> {
+        self(virt)
+    }
+}
+
+impl fmt::Debug for dyn VirtToPhys + Sync {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("VirtToPhys").finish_non_exhaustive()
+    }
+}
+
+/// A policy deciding how long to wait for a DMA operation to complete.
+///
+/// Without a policy, DMA operations wait forever.
+/// See [`FwCfg::set_dma_poll`] for how DMA operations change with a policy.
+///
+/// This is implemented for closures taking the number of polls so far,
+/// which may also check a clock or yield to a scheduler, for example:
+/// ```
+/// use qemu_fw_cfg::{FwCfg, PollBudget};
+///
+/// let mut fw_cfg = unsafe { FwCfg::take().unwrap() };
+/// // Give up after a million polls
+/// fw_cfg.set_dma_poll(&PollBudget(1_000_000));
+/// // Give up after a deadline, yielding between polls
+/// fw_cfg.set_dma_poll(&|_| {
+///     yield_now();
+///     now() < deadline()
+/// });
+/// ```
+pub trait DmaPoll {
+    /// Called each time a DMA operation is found incomplete,
+    /// with the number of times it has been polled so far.
+    ///
+    /// Return `false` to give up with [`Error::Timeout`].
+    fn keep_polling(&self, polls: u64) -> bool;
+}
+
+impl<F: Fn(u64) -> bool> DmaPoll for F {
+    fn keep_polling(&self, polls: u64) -> bool {
+        self(polls)
+    }
+}
+
+impl fmt::Debug for dyn DmaPoll + Sync {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("DmaPoll").finish_non_exhaustive()
+    }
+}
+
+/// Cache maintenance for devices that do not snoop CPU caches during DMA,
+/// such as devices not marked `dma-coherent` in the device tree.
+///
+/// Without cache maintenance, DMA is assumed to be cache-coherent.
+///
+/// Descriptors and payloads are cleaned before the device reads them, and
+/// invalidated before reading what the device wrote. Since invalidating
+/// discards whole cache lines, DMA should be staged through a buffer aligned
+/// to cache lines (see [`FwCfg::set_dma_buffer`]) with direct DMA disallowed
+/// (see [`FwCfg::set_direct_dma`]).
+pub trait DmaCache {
+    /// Write cached data of `len` bytes at `ptr` back to memory.
+    fn clean(&self, ptr: *const u8, len: usize);
+
+    /// Discard cached data of `len` bytes at `ptr`,
+    /// so that the following reads come from memory.
+    fn invalidate(&self, ptr: *const u8, len: usize);
+}
+
+impl fmt::Debug for dyn DmaCache + Sync {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("DmaCache").finish_non_exhaustive()
+    }
+}
+
+/// A [`DmaPoll`] policy giving up after a fixed number of polls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PollBudget(pub u64);
+
+impl DmaPoll for PollBudget {
+    fn keep_polling(&self, polls: u64) -> bool {
+        polls < self.0
+    }
+}
+
+impl FwCfg {
+    /// Register a buffer shared with the host for staging DMA transfers.
+    ///
+    /// Once registered, every DMA descriptor and payload is copied through
+    /// this buffer instead of being accessed by the device where it lives.
+    /// This is necessary for confidential guests (such as AMD SEV or Intel TDX),
+    /// where the host cannot access private guest memory.
+    ///
+    /// File reads also use DMA through this buffer when the device supports it,
+    /// and fall back to the data register when no buffer is registered.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be valid for reads and writes of `len` bytes and must be
+    /// accessible by the device (e.g. mapped as shared/decrypted) until the
+    /// buffer is removed or this `FwCfg` is dropped.
+    /// Nothing else may access the buffer during that time.
+    ///
+    /// If this `FwCfg` is dropped while a DMA operation staged through the
+    /// buffer is still in progress after it timed out, the device may access
+    /// the buffer at any time later, so it must never be used again.
+    ///
+    /// Returns [`Error::DmaPending`] without registering the buffer if a DMA
+    /// operation that timed out is still in progress.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the buffer is too small to hold a DMA descriptor
+    /// followed by at least one byte of payload.
+    pub unsafe fn set_dma_buffer(&mut self, ptr: *mut u8, len: usize) -> Result<(), Error> {
+        let buffer = DmaBuffer { ptr, len };
+        assert!(buffer.payload_capacity() > 0, "DMA buffer is too small");
+        self.check_dma_pending()?;
+        self.dma_buffer = Some(buffer);
+        Ok(())
+    }
+
+    /// Stop using the buffer registered with [`FwCfg::set_dma_buffer`].
+    ///
+    /// Returns [`Error::DmaPending`] if a DMA operation that timed out is
+    /// still in progress, in which case the buffer is still in use.
+    pub fn remove_dma_buffer(&mut self) -> Result<(), Error> {
+        self.check_dma_pending()?;
+        self.dma_buffer = None;
+        Ok(())
+    }
+
+    /// Allow or disallow DMA to access memory outside the registered DMA buffer.
+    ///
+    /// This is allowed by default. Confidential guests should disallow it so
+    /// that DMA operations fail with [`Error::DmaNotAvailable`]
+    /// instead of pointing the device at private memory when no DMA buffer
+    /// is registered.
+    pub fn set_direct_dma(&mut self, allowed: bool) {
+        self.direct_dma = allowed;
+    }
+
+    /// Translate every address given to the device during DMA with `translator`.
+    ///
+    /// This is necessary when memory is not identity-mapped,
+    /// such as in a higher-half kernel.
+    pub fn set_virt_to_phys(&mut self, translator: &'static (dyn VirtToPhys + Sync)) {
+        self.virt_to_phys = Some(translator);
+    }
+
+    /// Stop translating addresses, assuming that memory is identity-mapped.
+    pub fn remove_virt_to_phys(&mut self) {
+        self.virt_to_phys = None;
+    }
+
+    /// Wait for DMA operations to complete according to `poll`.
+    ///
+    /// The policy is called each time any DMA operation is found incomplete,
+    /// so it can also yield, and every DMA operation can time out with
+    /// [`Error::Timeout`]. Since the device may keep accessing memory after
+    /// giving up, DMA operations are then staged through the buffer
+    /// registered with [`FwCfg::set_dma_buffer`], or through a small buffer
+    /// owned by this crate if none is registered and direct DMA is allowed,
+    /// but never through memory that is only borrowed. For the same reason,
+    /// [`FwCfg::read_file_to_phys`] does not use DMA directly into the
+    /// destination while a policy is set.
+    pub fn set_dma_poll(&mut self, poll: &'static (dyn DmaPoll + Sync)) {
+        self.dma_poll = Some(poll);
+    }
+
+    /// Wait forever for DMA operations to complete.
+    pub fn remove_dma_poll(&mut self) {
+        self.dma_poll = None;
+    }
+
+    /// Maintain CPU caches around DMA operations with `cache`.
+    pub fn set_dma_cache(&mut self, cache: &'static (dyn DmaCache + Sync)) {
+        self.dma_cache = Some(cache);
+    }
+
+    /// Stop maintaining CPU caches, assuming that DMA is cache-coherent.
+    pub fn remove_dma_cache(&mut self) {
+        self.dma_cache = None;
+    }
+
+    fn cache_clean(&self, ptr: *const u8, len: usize) {
+        if let Some(cache) = self.dma_cache {
+            cache.clean(ptr, len);
+        }
+    }
+
+    fn cache_invalidate(&self, ptr: *const u8, len: usize) {
+        if let Some(cache) = self.dma_cache {
+            cache.invalidate(ptr, len);
+        }
+    }
+
+    /// Read a file into `len` bytes of physical memory at `phys_addr`,
+    /// without copying it through other memory.
+    ///
+    /// This uses DMA directly into the destination when possible, which is
+    /// not the case while a [`DmaPoll`] policy is set. Otherwise,
+    /// the destination is accessed where it is mapped, as given by
+    /// [`VirtToPhys::phys_to_virt`] or identity-mapped without a translator,
+    /// and [`Error::AddressNotMapped`] is returned if it is not.
+    ///
+    /// Returns [`Error::SizeMismatch`] if `len` is not the size of the file.
+    ///
+    /// # Safety
+    ///
+    /// The physical range must be valid for writes of `len` bytes by the
+    /// device and not be in use by anything else. Where it is mapped,
+    /// it must be mapped contiguously.
+    pub unsafe fn read_file_to_phys(
+        &mut self,
+        file: &FwCfgFile,
+        phys_addr: u64,
+        len: usize,
+    ) -> Result<(), Error> {
+        if len != file.size() {
+            return Err(Error::SizeMismatch);
+        }
+        if !self.has_dma() || self.staging_buffer().is_some() || !self.direct_dma {
+            let data = self.phys_to_virt(phys_addr)? as *mut u8;
+            return self.read_item(file.key(), data, len);
+        }
+
+        // Cache maintenance needs to know where the destination is mapped.
+        let data = match self.dma_cache {
+            Some(_) => Some(self.phys_to_virt(phys_addr)? as *const u8),
+            None => None,
+        };
+        if let Some(data) = data {
+            self.cache_clean(data, len);
+        }
+        let control = FwCfgDmaAccess::select(file.key()) | FwCfgDmaAccess::READ;
+        let mut local = MaybeUninit::uninit();
+        // The length fits since it is the size of the file.
+        let access = local.write(FwCfgDmaAccess::new(control, phys_addr, len as u32));
+        self.run_dma(access, false)?;
+        if let Some(data) = data {
+            self.cache_invalidate(data, len);
+        }
+        Ok(())
+    }
+
+    fn phys_to_virt(&self, phys: u64) -> Result<usize, Error> {
+        match self.virt_to_phys {
+            Some(translator) => translator.phys_to_virt(phys),
+            None => usize::try_from(phys).ok(),
+        }
+        .ok_or(Error::AddressNotMapped)
+    }
+
+    /// Return the physical address of `len` bytes at `ptr`,
+    /// making sure that they are physically contiguous.
+    fn dma_address(&self, ptr: *const u8, len: usize) -> Result<u64, Error> {
+        let virt = ptr as usize;
+        let translator = match self.virt_to_phys {
+            Some(translator) => translator,
+            None => return Ok(virt as u64),
+        };
+        let phys = translator
+            .virt_to_phys(virt)
+            .ok_or(Error::AddressNotMapped)?;
+
+        let end = virt.checked_add(len).ok_or(Error::AddressNotMapped)?;
+        let page_size = translator.page_size();
+        let mut page = virt - virt % page_size;
+        while let Some(next_page) = page.checked_add(page_size).filter(|&page| page < end) {
+            page = next_page;
+            let expected = phys + (page - virt) as u64;
+            match translator.virt_to_phys(page) {
+                Some(actual) if actual == expected => {}
+                Some(_) => return Err(Error::NonContiguousBuffer),
+                None => return Err(Error::AddressNotMapped),
+            }
+        }
+
+        Ok(phys)
+    }
+
+    pub(crate) fn has_dma(&self) -> bool {
+        let device_has_dma = match &self.mode {
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Mode::IOPort => true,
+            Mode::MemoryMapped(device) => device.has_dma(),
+        };
+        device_has_dma && self.quirks.dma && self.features.contains(FwCfgFeatures::DMA)
+    }
+
+    /// Run a DMA operation on `len` bytes at `data`,
+    /// staging it through the DMA buffer if one is registered.
+    pub(crate) fn dma(&mut self, control: u32, data: *mut u8, len: usize) -> Result<(), Error> {
+        self.dma_after(control, data, len, false)
+    }
+
+    /// Like [`FwCfg::dma`], but `address_reset` tells whether another
+    /// DMA operation completed right before this one.
+    pub(crate) fn dma_after(
+        &mut self,
+        control: u32,
+        data: *mut u8,
+        len: usize,
+        mut address_reset: bool,
+    ) -> Result<(), Error> {
+        let mut transfer = Transfer::new(self, control, data, len)?;
+        let mut local = MaybeUninit::uninit();
+        loop {
+            unsafe {
+                let access = transfer.prepare(self, local.as_mut_ptr())?;
+                self.run_dma(access, address_reset)?;
+                if transfer.complete(self) {
+                    return Ok(());
+                }
+            }
+            address_reset = true;
+        }
+    }
+
+    /// Start the DMA operation described by `access` and wait for it to complete.
+    unsafe fn run_dma(
+        &mut self,
+        access: *const FwCfgDmaAccess,
+        address_reset: bool,
+    ) -> Result<(), Error> {
+        self.start_dma(access, address_reset)?;
+        let mut polls = 0;
+        let result = loop {
+            if let Some(result) = self.poll_dma(access) {
+                break result;
+            }
+            polls += 1;
+            if self.give_up_dma(access, polls) {
+                break Err(Error::Timeout);
+            }
+        };
+        if result.is_err() {
+            self.position = None;
+        }
+        result
+    }
+
+    /// Start the DMA operation described by `access`.
+    ///
+    /// QEMU resets the DMA address register after each transfer. If that is
+    /// known to have happened, `address_reset` skips writing its upper half
+    /// when it is zero, unless disabled by
+    /// [`FwCfgQuirks::dma_address_reset`](crate::FwCfgQuirks::dma_address_reset).
+    pub(crate) unsafe fn start_dma(
+        &mut self,
+        access: *const FwCfgDmaAccess,
+        address_reset: bool,
+    ) -> Result<(), Error> {
+        let address = self.dma_address(access.cast(), size_of::<FwCfgDmaAccess>())?;
+        let address_reset = address_reset && self.quirks.dma_address_reset;
+        // Assume success, callers forget the position if it fails.
+        self.advance_position((*access).control(), (*access).length());
+        self.cache_clean(access.cast(), size_of::<FwCfgDmaAccess>());
+        // The descriptor and payload must not be reordered to after this:
+        dma_wmb();
+        match &mut self.mode {
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Mode::IOPort => crate::arch::start_dma(address, address_reset),
+            Mode::MemoryMapped(device) => device.start_dma(address, address_reset),
+        }
+        Ok(())
+    }
+
+    /// Return the result of the DMA operation described by `access`,
+    /// or `None` if it is still in progress.
+    pub(crate) unsafe fn poll_dma(
+        &self,
+        access: *const FwCfgDmaAccess,
+    ) -> Option<Result<(), Error>> {
+        self.cache_invalidate(access.cast(), size_of::<FwCfgDmaAccess>());
+        let control = (*access).read_control();
+        if (control & FwCfgDmaAccess::ERROR) != 0 {
+            return Some(Err(Error::DmaFailed));
+        }
+        if control != 0 {
+            return None;
+        }
+        // The payload must not be read before DMA completes:
+        dma_rmb();
+        Some(Ok(()))
+    }
+
+    /// The buffer DMA operations are staged through: the registered DMA
+    /// buffer, or the bounce buffer if a [`DmaPoll`] policy may give up
+    /// on direct DMA.
+    pub(crate) fn staging_buffer(&self) -> Option<DmaBuffer> {
+        match self.dma_buffer {
+            Some(buffer) => Some(buffer),
+            None if self.direct_dma && self.dma_poll.is_some() => Some(DmaBuffer {
+                ptr: BOUNCE_BUFFER.0.get().cast(),
+                len: BOUNCE_BUFFER_SIZE,
+            }),
+            None => None,
+        }
+    }
+
+    /// Return whether to give up on the DMA operation described by `access`
+    /// after polling it `polls` times, according to the [`DmaPoll`] policy.
+    pub(crate) fn give_up_dma(&mut self, access: *const FwCfgDmaAccess, polls: u64) -> bool {
+        let keep_polling = match self.dma_poll {
+            Some(poll) => poll.keep_polling(polls),
+            None => {
+                spin_loop();
+                true
+            }
+        };
+        !keep_polling && self.abandon_dma(access)
+    }
+
+    /// Stop waiting for the DMA operation described by `access`, leaving its
+    /// buffer pending until the device completes it.
+    ///
+    /// Returns `false` if the operation is not staged, as the device
+    /// accesses memory that is only borrowed and must be waited for.
+    pub(crate) fn abandon_dma(&mut self, access: *const FwCfgDmaAccess) -> bool {
+        match self.staging_buffer() {
+            Some(buffer) if ptr::eq(buffer.access(), access) => {
+                self.dma_pending = Some(buffer);
+                self.position = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Return [`Error::DmaPending`] if a DMA operation that timed out
+    /// is still in progress.
+    pub(crate) fn check_dma_pending(&mut self) -> Result<(), Error> {
+        let buffer = match self.dma_pending {
+            Some(buffer) => buffer,
+            None => return Ok(()),
+        };
+        if unsafe { self.poll_dma(buffer.access()) }.is_none() {
+            return Err(Error::DmaPending);
+        }
+        self.dma_pending = None;
+        Ok(())
+    }
+}
+
+/// A DMA operation on a buffer, split into chunks when staged through the DMA buffer
+/// or the bounce buffer, or when longer than a DMA descriptor can describe.
+#[derive(Debug)]
+pub(crate) struct Transfer {
+    control: u32,
+    data: *mut u8,
+    len: usize,
+    done: usize,
+    chunk: usize,
+    payload: *mut u8,
+    staged: Option<DmaBuffer>,
+}
+
+impl Transfer {
+    pub(crate) fn new(
+        fw_cfg: &mut FwCfg,
+        control: u32,
+        data: *mut u8,
+        len: usize,
+    ) -> Result<Self, Error> {
+        let staged = fw_cfg.staging_buffer();
+        if !fw_cfg.has_dma() || (staged.is_none() && !fw_cfg.direct_dma) {
+            return Err(Error::DmaNotAvailable);
+        }
+        fw_cfg.check_dma_pending()?;
+        Ok(Self {
+            control,
+            data,
+            len,
+            done: 0,
+            chunk: 0,
+            payload: ptr::null_mut(),
+            staged,
+        })
+    }
+
+    /// Set up the descriptor for the next chunk and return its address,
+    /// which is either `local` or inside the DMA buffer.
+    pub(crate) unsafe fn prepare(
+        &mut self,
+        fw_cfg: &FwCfg,
+        local: *mut FwCfgDmaAccess,
+    ) -> Result<*const FwCfgDmaAccess, Error> {
+        // The length in a DMA descriptor is 32 bits.
+        let remaining = (self.len - self.done).min(u32::MAX as usize);
+        let has_payload = (self.control & (FwCfgDmaAccess::READ | FwCfgDmaAccess::WRITE)) != 0;
+        let (access, payload, chunk) = match self.staged {
+            Some(buffer) if has_payload => {
+                let chunk = remaining.min(buffer.payload_capacity());
+                if (self.control & FwCfgDmaAccess::WRITE) != 0 {
+                    ptr::copy_nonoverlapping(self.data.add(self.done), buffer.payload(), chunk);
+                }
+                (buffer.access(), buffer.payload(), chunk)
+            }
+            Some(buffer) => (buffer.access(), self.data, remaining),
+            None => (local, self.data.add(self.done), remaining),
+        };
+        let address = if has_payload {
+            fw_cfg.cache_clean(payload, chunk);
+            fw_cfg.dma_address(payload, chunk)?
+        } else {
+            0
+        };
+        access.write(FwCfgDmaAccess::new(self.control, address, chunk as u32));
+        self.chunk = chunk;
+        self.payload = payload;
+        Ok(access)
+    }
+
+    /// Finish the chunk after its DMA operation succeeded.
+    /// Return `true` once the entire buffer is transferred.
+    pub(crate) unsafe fn complete(&mut self, fw_cfg: &FwCfg) -> bool {
+        if (self.control & FwCfgDmaAccess::READ) != 0 {
+            fw_cfg.cache_invalidate(self.payload, self.chunk);
+            if self.staged.is_some() {
+                ptr::copy_nonoverlapping(self.payload, self.data.add(self.done), self.chunk);
+            }
+        }
+        self.done += self.chunk;
+        // Later chunks continue where the previous one stopped.
+        self.control &= !FwCfgDmaAccess::SELECT;
+        self.done == self.len
+    }
+}
+
+impl DmaBuffer {
+    fn access_offset(&self) -> usize {
+        self.ptr.align_offset(align_of::<FwCfgDmaAccess>())
+    }
+
+    fn access(&self) -> *mut FwCfgDmaAccess {
+        unsafe { self.ptr.add(self.access_offset()).cast() }
+    }
+
+    fn payload(&self) -> *mut u8 {
+        unsafe { self.access().add(1).cast() }
+    }
+
+    fn payload_capacity(&self) -> usize {
+        let header = self
+            .access_offset()
+            .saturating_add(size_of::<FwCfgDmaAccess>());
+        self.len.saturating_sub(header)
+    }
+}
+
+#[derive(Debug)]
+// NOTE: The memory layout of this struct must match this exactly:
+// https://gitlab.com/qemu-project/qemu/-/blob/v7.0.0/docs/specs/fw_cfg.txt#L177-181
+#[repr(C)]
+pub(crate) struct FwCfgDmaAccess {
+    control_be: UnsafeCell<u32>,
+    length_be: u32,
+    address_be: u64,
+}
+
+impl FwCfgDmaAccess {
+    pub(crate) const ERROR: u32 = 1 << 0;
+    pub(crate) const READ: u32 = 1 << 1;
+    pub(crate) const SKIP: u32 = 1 << 2;
+    pub(crate) const SELECT: u32 = 1 << 3;
+    pub(crate) const WRITE: u32 = 1 << 4;
+
+    /// Return the control bits selecting `key`.
+    pub(crate) fn select(key: FwCfgKey) -> u32 {
+        (key.0 as u32) << 16 | Self::SELECT
+    }
+
+    fn new(control: u32, address: u64, length: u32) -> Self {
+        Self {
+            control_be: UnsafeCell::new(control.to_be()),
+            length_be: length.to_be(),
+            address_be: address.to_be(),
+        }
+    }
+
+    fn read_control(&self) -> u32 {
+        u32::from_be(unsafe { self.control_be.get().read_volatile() })
+    }
+
+    /// Return the control field as written, before the device runs.
+    fn control(&self) -> u32 {
+        u32::from_be(unsafe { *self.control_be.get() })
+    }
+
+    fn length(&self) -> usize {
+        u32::from_be(self.length_be) as usize
+    }
+}
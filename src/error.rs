@@ -0,0 +1,117 @@
+use core::fmt;
+
+/// An enum type for all errors of this crate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error {
+    /// Invalid signature returned from QEMU fw_cfg I/O port
+    InvalidSignature,
+    /// The DMA interface is advertised, but its register
+    /// does not return the expected signature
+    InvalidDmaSignature,
+    /// Another `FwCfg` value taken with [`FwCfg::take`](crate::FwCfg::take) still exists
+    AlreadyTaken,
+    /// The [`MmioLayout`](crate::MmioLayout) has an unsupported data width
+    /// or misaligned registers
+    InvalidLayout,
+    /// This fw_cfg device does not support DMA access,
+    /// which is necessary for writing since QEMU v2.4.
+    ///
+    /// How files are written depends on the QEMU version:
+    /// - before v2.4, through the data register, which is only done
+    ///   when enabled with [`FwCfg::set_legacy_writes`](crate::FwCfg::set_legacy_writes)
+    /// - from v2.4 to v2.8, not at all, as the data register ignores writes
+    ///   and the DMA interface does not exist yet
+    /// - since v2.9, through the DMA interface
+    ///
+    /// This is also returned when direct DMA is disallowed and no DMA buffer
+    /// is registered, see [`FwCfg::set_direct_dma`](crate::FwCfg::set_direct_dma).
+    DmaNotAvailable,
+    /// The device reported an error during a DMA transfer
+    DmaFailed,
+    /// A buffer is not mapped according to the [`VirtToPhys`](crate::VirtToPhys) translator
+    AddressNotMapped,
+    /// A buffer spans physical pages that are not contiguous
+    NonContiguousBuffer,
+    /// The DMA operation did not complete before the [`DmaPoll`](crate::DmaPoll) policy gave up.
+    ///
+    /// The device may still complete the operation later. Until it does,
+    /// further operations on the device fail with [`Error::DmaPending`].
+    Timeout,
+    /// A DMA operation that timed out is still in progress
+    DmaPending,
+    /// The length of the destination does not match the size of the item
+    SizeMismatch,
+    /// The buffer is smaller than an item that must be read entirely,
+    /// such as the IGD OpRegion, so only part of it was read
+    TruncatedBuffer {
+        /// The number of bytes copied into the buffer
+        copied: usize,
+        /// The size of the item
+        size: usize,
+    },
+    /// The directory has more entries than
+    /// [`FwCfgLimits::max_entries`](crate::FwCfgLimits::max_entries)
+    TooManyEntries,
+    /// The item is larger than
+    /// [`FwCfgLimits::max_item_size`](crate::FwCfgLimits::max_item_size)
+    ItemTooLarge,
+    /// A directory entry has an invalid name
+    InvalidName,
+    /// A name appears more than once in the directory
+    DuplicateName,
+    /// Memory for the item could not be allocated
+    OutOfMemory,
+    /// A block or offset is past the end of the item
+    OutOfRange,
+    /// The device cannot be written to
+    ReadOnly,
+    /// The IGD OpRegion is missing or does not start with its signature
+    InvalidOpRegion,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidSignature => f.write_str("invalid fw_cfg signature"),
+            Error::InvalidDmaSignature => f.write_str("invalid fw_cfg DMA signature"),
+            Error::AlreadyTaken => f.write_str("fw_cfg is already taken"),
+            Error::InvalidLayout => f.write_str("invalid MMIO register layout"),
+            Error::DmaNotAvailable => f.write_str("DMA is not available"),
+            Error::DmaFailed => f.write_str("DMA transfer failed"),
+            Error::AddressNotMapped => f.write_str("buffer address is not mapped"),
+            Error::NonContiguousBuffer => f.write_str("buffer is not physically contiguous"),
+            Error::Timeout => f.write_str("DMA transfer timed out"),
+            Error::DmaPending => f.write_str("a timed out DMA transfer is still in progress"),
+            Error::SizeMismatch => f.write_str("destination size does not match the item"),
+            Error::TruncatedBuffer { copied, size } => {
+                write!(f, "buffer truncated, copied {} of {} bytes", copied, size)
+            }
+            Error::TooManyEntries => f.write_str("too many directory entries"),
+            Error::ItemTooLarge => f.write_str("item is too large"),
+            Error::InvalidName => f.write_str("invalid file name"),
+            Error::DuplicateName => f.write_str("duplicate file name"),
+            Error::OutOfMemory => f.write_str("out of memory"),
+            Error::OutOfRange => f.write_str("out of range"),
+            Error::ReadOnly => f.write_str("device is read-only"),
+            Error::InvalidOpRegion => f.write_str("invalid IGD OpRegion"),
+        }
+    }
+}
+
+impl core::error::Error for Error {}
+
+#[cfg(feature = "embedded-io")]
+impl embedded_io::Error for Error {
+    fn kind(&self) -> embedded_io::ErrorKind {
+        use embedded_io::ErrorKind;
+
+        match self {
+            Error::Timeout => ErrorKind::TimedOut,
+            Error::OutOfMemory => ErrorKind::OutOfMemory,
+            Error::OutOfRange => ErrorKind::InvalidInput,
+            Error::ReadOnly => ErrorKind::Unsupported,
+            _ => ErrorKind::Other,
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use core::fmt;
+use core::ops::{BitAnd, BitOr};
+
+/// The features supported by a fw_cfg device,
+/// read from [`FwCfgKey::ID`](crate::FwCfgKey::ID).
+///
+/// See [`FwCfg::features`](crate::FwCfg::features).
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct FwCfgFeatures(u32);
+
+impl FwCfgFeatures {
+    /// The traditional selector and data registers
+    pub const TRADITIONAL_INTERFACE: Self = Self(1 << 0);
+    /// The DMA interface, supported by QEMU since version 2.9
+    pub const DMA: Self = Self(1 << 1);
+
+    /// No features.
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    /// Build features from their raw bits, keeping unknown bits.
+    pub const fn from_bits_retain(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    /// The raw bits of these features.
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+
+    /// Return `true` if no features are set.
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Return `true` if all features in `other` are set.
+    pub const fn contains(self, other: Self) -> bool {
+        (self.0 & other.0) == other.0
+    }
+}
+
+impl BitOr for FwCfgFeatures {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitAnd for FwCfgFeatures {
+    type Output = Self;
+
+    fn bitand(self, rhs: Self) -> Self {
+        Self(self.0 & rhs.0)
+    }
+}
+
+impl fmt::Debug for FwCfgFeatures {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let mut set = fmt.debug_set();
+        if self.contains(Self::TRADITIONAL_INTERFACE) {
+            set.entry(&format_args!("TRADITIONAL_INTERFACE"));
+        }
+        if self.contains(Self::DMA) {
+            set.entry(&format_args!("DMA"));
+        }
+        let unknown = self.0 & !(Self::TRADITIONAL_INTERFACE | Self::DMA).0;
+        if unknown != 0 {
+            set.entry(&format_args!("{:#x}", unknown));
+        }
+        set.finish()
+    }
+}
@@ -0,0 +1,175 @@
+use crate::dma::{FwCfgDmaAccess, Transfer};
+use crate::{Error, FwCfg, FwCfgFile};
+use core::future::Future;
+use core::hint::spin_loop;
+use core::marker::{PhantomData, PhantomPinned};
+use core::mem::{self, MaybeUninit};
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+impl FwCfg {
+    /// Read a file into `buffer` using DMA, without blocking.
+    ///
+    /// This fills up to `buffer.len()` bytes, and a shorter buffer is not an
+    /// error. See [`DmaFuture`] for how the transfer progresses.
+    ///
+    /// # Safety
+    ///
+    /// The returned future must be dropped before `buffer` is used again or
+    /// freed, and must not be leaked (e.g. with [`core::mem::forget`]) while
+    /// the transfer is in progress, as the device keeps writing to `buffer`
+    /// until it completes.
+    pub unsafe fn read_dma<'a>(
+        &'a mut self,
+        file: &FwCfgFile,
+        buffer: &'a mut [u8],
+    ) -> DmaFuture<'a> {
+        let len = file.size().min(buffer.len());
+        let control = FwCfgDmaAccess::select(file.key()) | FwCfgDmaAccess::READ;
+        DmaFuture::new(self, control, buffer.as_mut_ptr(), len)
+    }
+
+    /// Write provided `data` into a file using DMA, starting at file offset 0,
+    /// without blocking.
+    ///
+    /// See [`DmaFuture`] for how the transfer progresses.
+    ///
+    /// # Safety
+    ///
+    /// The returned future must be dropped before `data` is modified or
+    /// freed, and must not be leaked (e.g. with [`core::mem::forget`]) while
+    /// the transfer is in progress, as the device keeps reading `data`
+    /// until it completes.
+    pub unsafe fn write_dma<'a>(&'a mut self, file: &FwCfgFile, data: &'a [u8]) -> DmaFuture<'a> {
+        let control = FwCfgDmaAccess::select(file.key()) | FwCfgDmaAccess::WRITE;
+        DmaFuture::new(self, control, data.as_ptr() as *mut u8, data.len())
+    }
+}
+
+/// A future for a DMA transfer started by [`FwCfg::read_dma`] or [`FwCfg::write_dma`].
+///
+/// The transfer starts when the future is first polled, and each poll checks
+/// whether it has completed. The future wakes itself while the transfer is in
+/// progress since fw_cfg does not raise interrupts, so it works with any
+/// executor. The [`DmaPoll`](crate::DmaPoll) policy is consulted on each poll.
+///
+/// The DMA descriptor lives inside the future, which is why it must be pinned,
+/// and the buffer stays borrowed until the future is dropped. Dropping the
+/// future while the transfer is in progress waits for it to complete, so the
+/// device never accesses memory that is no longer in use, unless the transfer
+/// is staged (see [`FwCfg::set_dma_poll`]): it is then left in progress like
+/// a transfer that timed out.
+#[must_use = "futures do nothing unless polled"]
+#[derive(Debug)]
+pub struct DmaFuture<'a> {
+    fw_cfg: &'a mut FwCfg,
+    transfer: Option<Transfer>,
+    state: State,
+    local: MaybeUninit<FwCfgDmaAccess>,
+    _buffer: PhantomData<&'a mut [u8]>,
+    _pinned: PhantomPinned,
+}
+
+#[derive(Debug)]
+enum State {
+    Failed(Error),
+    Idle,
+    Running {
+        access: *const FwCfgDmaAccess,
+        polls: u64,
+    },
+    Done,
+}
+
+impl<'a> DmaFuture<'a> {
+    fn new(fw_cfg: &'a mut FwCfg, control: u32, data: *mut u8, len: usize) -> Self {
+        let (transfer, state) = match Transfer::new(fw_cfg, control, data, len) {
+            Ok(transfer) => (Some(transfer), State::Idle),
+            Err(error) => (None, State::Failed(error)),
+        };
+        Self {
+            fw_cfg,
+            transfer,
+            state,
+            local: MaybeUninit::uninit(),
+            _buffer: PhantomData,
+            _pinned: PhantomPinned,
+        }
+    }
+
+    fn step(&mut self) -> Poll<Result<(), Error>> {
+        loop {
+            match mem::replace(&mut self.state, State::Done) {
+                State::Failed(error) => return Poll::Ready(Err(error)),
+                State::Done => panic!("`DmaFuture` polled after completion"),
+                State::Idle => {
+                    let transfer = self.transfer.as_mut().unwrap();
+                    let access = unsafe { transfer.prepare(self.fw_cfg, self.local.as_mut_ptr()) };
+                    let access = match access {
+                        Ok(access) => access,
+                        Err(error) => return Poll::Ready(Err(error)),
+                    };
+                    if let Err(error) = unsafe { self.fw_cfg.start_dma(access, false) } {
+                        return Poll::Ready(Err(error));
+                    }
+                    self.state = State::Running { access, polls: 0 };
+                }
+                State::Running { access, polls } => match unsafe { self.fw_cfg.poll_dma(access) } {
+                    None => {
+                        let polls = polls + 1;
+                        if self.fw_cfg.give_up_dma(access, polls) {
+                            return Poll::Ready(Err(Error::Timeout));
+                        }
+                        self.state = State::Running { access, polls };
+                        return Poll::Pending;
+                    }
+                    Some(Err(error)) => {
+                        self.fw_cfg.position = None;
+                        return Poll::Ready(Err(error));
+                    }
+                    Some(Ok(())) => {
+                        let transfer = self.transfer.as_mut().unwrap();
+                        if unsafe { transfer.complete(self.fw_cfg) } {
+                            return Poll::Ready(Ok(()));
+                        }
+                        self.state = State::Idle;
+                    }
+                },
+            }
+        }
+    }
+}
+
+impl Future for DmaFuture<'_> {
+    type Output = Result<(), Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // Nothing is moved out of the future.
+        let this = unsafe { self.get_unchecked_mut() };
+        let poll = this.step();
+        if poll.is_pending() {
+            cx.waker().wake_by_ref();
+        }
+        poll
+    }
+}
+
+impl Drop for DmaFuture<'_> {
+    fn drop(&mut self) {
+        if let State::Running { access, .. } = self.state {
+            if self.fw_cfg.abandon_dma(access) {
+                return;
+            }
+            loop {
+                match unsafe { self.fw_cfg.poll_dma(access) } {
+                    None => spin_loop(),
+                    Some(Ok(())) => break,
+                    Some(Err(_)) => {
+                        self.fw_cfg.position = None;
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
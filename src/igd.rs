@@ -0,0 +1,179 @@
+//! Support for Intel integrated graphics (IGD) passthrough.
+//!
+//! When an IGD is assigned to a guest with VFIO, QEMU provides two files:
+//! - [`OPREGION_FILE`], a copy of the host OpRegion, which the firmware
+//!   copies into reserved memory (ACPI NVS on x86) and whose address it
+//!   writes to the [`ASLS`] register of the device
+//! - [`BDSM_SIZE_FILE`], the size of the stolen memory, which the firmware
+//!   reserves with an alignment of [`BDSM_ALIGNMENT`] and whose address it
+//!   writes to the [`BDSM`] register of the device
+//!
+//! Writes to the PCI configuration space of the device, usually at 00:02.0,
+//! are left to the caller through [`IgdConfigSpace`].
+//!
+//! # Examples
+//! ```
+//! use qemu_fw_cfg::igd::{self, Igd};
+//! use qemu_fw_cfg::FwCfg;
+//!
+//! let mut fw_cfg = unsafe { FwCfg::take().unwrap() };
+//! let igd = Igd::find(&mut fw_cfg).unwrap();
+//! if let Some(size) = igd.opregion_size() {
+//!     let (address, memory) = allocate_acpi_nvs(size);
+//!     igd.read_opregion(&mut fw_cfg, memory).unwrap();
+//!     igd::set_opregion_address(&mut config_space, address);
+//! }
+//! if let Some(size) = igd.bdsm_size() {
+//!     let address = allocate_reserved(size, igd::BDSM_ALIGNMENT);
+//!     igd::set_bdsm_address(&mut config_space, address);
+//! }
+//! ```
+//
+// https://gitlab.com/qemu-project/qemu/-/blob/v7.0.0/docs/igd-assign.txt
+
+use crate::{Error, FwCfg, FwCfgFile};
+
+/// The name of the file holding the OpRegion.
+pub const OPREGION_FILE: &str = "etc/igd-opregion";
+
+/// The name of the file holding the size of the stolen memory,
+/// as a little-endian `u64`.
+pub const BDSM_SIZE_FILE: &str = "etc/igd-bdsm-size";
+
+/// The signature at the start of the OpRegion header.
+pub const OPREGION_SIGNATURE: &[u8; 16] = b"IntelGraphicsMem";
+
+/// The offset of the ASL Storage register in the PCI configuration space,
+/// holding the address of the OpRegion.
+pub const ASLS: u8 = 0xfc;
+
+/// The offset of the Base of Data Stolen Memory register in the PCI
+/// configuration space, holding the address of the stolen memory.
+///
+/// This is the register of devices before Gen 11.
+pub const BDSM: u8 = 0x5c;
+
+/// The alignment of the stolen memory.
+pub const BDSM_ALIGNMENT: u64 = 1 << 20;
+
+/// Access to the PCI configuration space of the IGD, supplied by the caller.
+pub trait IgdConfigSpace {
+    /// Write `value` to the 32-bit register at `offset`.
+    fn write_u32(&mut self, offset: u8, value: u32);
+}
+
+/// The IGD passthrough files found in fw_cfg.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Igd {
+    opregion: Option<FwCfgFile>,
+    bdsm_size: Option<u64>,
+}
+
+impl Igd {
+    /// Find the IGD passthrough files and read the size of the stolen memory.
+    ///
+    /// Both are absent if no IGD is assigned to the guest.
+    /// Returns [`Error::SizeMismatch`] if [`BDSM_SIZE_FILE`] is not 8 bytes.
+    pub fn find(fw_cfg: &mut FwCfg) -> Result<Self, Error> {
+        let mut files = [(OPREGION_FILE, None), (BDSM_SIZE_FILE, None)];
+        fw_cfg.find_files(&mut files)?;
+        let [(_, opregion), (_, bdsm_size_file)] = files;
+
+        let bdsm_size = match bdsm_size_file {
+            Some(file) => {
+                if file.size() != 8 {
+                    return Err(Error::SizeMismatch);
+                }
+                let mut size = [0u8; 8];
+                fw_cfg.read_file_to_buffer(&file, &mut size)?;
+                Some(u64::from_le_bytes(size))
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            opregion,
+            bdsm_size,
+        })
+    }
+
+    /// The size of the OpRegion, which is how much memory to reserve for it.
+    pub fn opregion_size(&self) -> Option<usize> {
+        self.opregion.as_ref().map(FwCfgFile::size)
+    }
+
+    /// The size of the stolen memory to reserve.
+    pub fn bdsm_size(&self) -> Option<u64> {
+        self.bdsm_size
+    }
+
+    /// Read the OpRegion into `buffer`, usually the memory reserved for it,
+    /// and check its signature.
+    ///
+    /// Returns [`Error::TruncatedBuffer`] if `buffer` is smaller than
+    /// [`Igd::opregion_size`], and [`Error::InvalidOpRegion`] if the
+    /// signature does not match or there is no OpRegion.
+    pub fn read_opregion<'a>(
+        &self,
+        fw_cfg: &mut FwCfg,
+        buffer: &'a mut [u8],
+    ) -> Result<OpRegion<'a>, Error> {
+        let file = self.opregion.as_ref().ok_or(Error::InvalidOpRegion)?;
+        let copied = fw_cfg.read_file_to_buffer(file, buffer)?;
+        if copied < file.size() {
+            return Err(Error::TruncatedBuffer {
+                copied,
+                size: file.size(),
+            });
+        }
+        OpRegion::parse(&buffer[..copied])
+    }
+}
+
+/// An OpRegion whose header signature has been checked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpRegion<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> OpRegion<'a> {
+    /// Check that `data` starts with [`OPREGION_SIGNATURE`].
+    ///
+    /// Returns [`Error::InvalidOpRegion`] otherwise.
+    pub fn parse(data: &'a [u8]) -> Result<Self, Error> {
+        if !data.starts_with(OPREGION_SIGNATURE) {
+            return Err(Error::InvalidOpRegion);
+        }
+        Ok(Self { data })
+    }
+
+    /// The data of the OpRegion, including its header.
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// The size of the OpRegion in bytes.
+    pub fn size(&self) -> usize {
+        self.data.len()
+    }
+}
+
+/// Write the address of the OpRegion to the [`ASLS`] register.
+///
+/// The address must be below 4 GiB.
+pub fn set_opregion_address(config_space: &mut (impl IgdConfigSpace + ?Sized), address: u32) {
+    config_space.write_u32(ASLS, address);
+}
+
+/// Write the address of the stolen memory to the [`BDSM`] register.
+///
+/// # Panics
+///
+/// Panics if `address` is not aligned to [`BDSM_ALIGNMENT`].
+pub fn set_bdsm_address(config_space: &mut (impl IgdConfigSpace + ?Sized), address: u32) {
+    assert!(
+        address as u64 % BDSM_ALIGNMENT == 0,
+        "stolen memory is not aligned"
+    );
+    config_space.write_u32(BDSM, address);
+}
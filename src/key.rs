@@ -0,0 +1,108 @@
+use core::fmt;
+
+/// A selector key identifying a fw_cfg item.
+///
+/// Constants are provided for the keys documented by QEMU.
+/// Keys of files are found in [`FwCfgFile::key`](crate::FwCfgFile::key).
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FwCfgKey(pub u16);
+
+impl FwCfgKey {
+    /// The "QEMU" signature
+    pub const SIGNATURE: Self = Self(0x0000);
+    /// The feature bitmap
+    pub const ID: Self = Self(0x0001);
+    /// The UUID of the machine
+    pub const UUID: Self = Self(0x0002);
+    /// The size of RAM, in bytes
+    pub const RAM_SIZE: Self = Self(0x0003);
+    /// Whether graphics are disabled
+    pub const NOGRAPHIC: Self = Self(0x0004);
+    /// The number of CPUs at boot
+    pub const NB_CPUS: Self = Self(0x0005);
+    /// The machine ID
+    pub const MACHINE_ID: Self = Self(0x0006);
+    /// The load address of the kernel
+    pub const KERNEL_ADDR: Self = Self(0x0007);
+    /// The size of the kernel
+    pub const KERNEL_SIZE: Self = Self(0x0008);
+    /// The address of the kernel command line
+    pub const KERNEL_CMDLINE: Self = Self(0x0009);
+    /// The load address of the initrd
+    pub const INITRD_ADDR: Self = Self(0x000a);
+    /// The size of the initrd
+    pub const INITRD_SIZE: Self = Self(0x000b);
+    /// The boot device
+    pub const BOOT_DEVICE: Self = Self(0x000c);
+    /// The NUMA topology
+    pub const NUMA: Self = Self(0x000d);
+    /// Whether the boot menu is enabled
+    pub const BOOT_MENU: Self = Self(0x000e);
+    /// The maximum number of CPUs
+    pub const MAX_CPUS: Self = Self(0x000f);
+    /// The entry point of the kernel
+    pub const KERNEL_ENTRY: Self = Self(0x0010);
+    /// The data of the kernel
+    pub const KERNEL_DATA: Self = Self(0x0011);
+    /// The data of the initrd
+    pub const INITRD_DATA: Self = Self(0x0012);
+    /// The load address of the kernel command line
+    pub const CMDLINE_ADDR: Self = Self(0x0013);
+    /// The size of the kernel command line
+    pub const CMDLINE_SIZE: Self = Self(0x0014);
+    /// The data of the kernel command line
+    pub const CMDLINE_DATA: Self = Self(0x0015);
+    /// The load address of the kernel setup code
+    pub const SETUP_ADDR: Self = Self(0x0016);
+    /// The size of the kernel setup code
+    pub const SETUP_SIZE: Self = Self(0x0017);
+    /// The data of the kernel setup code
+    pub const SETUP_DATA: Self = Self(0x0018);
+    /// The file directory
+    pub const FILE_DIR: Self = Self(0x0019);
+    /// The first key used by files
+    pub const FILE_FIRST: Self = Self(0x0020);
+    /// The bit marking a key as selected for writing through the data register
+    pub const WRITE_CHANNEL: Self = Self(0x4000);
+    /// The bit marking a key as specific to an architecture
+    pub const ARCH_LOCAL: Self = Self(0x8000);
+
+    /// x86: Additional ACPI tables
+    pub const X86_ACPI_TABLES: Self = Self(Self::ARCH_LOCAL.0);
+    /// x86: SMBIOS entries
+    pub const X86_SMBIOS_ENTRIES: Self = Self(Self::ARCH_LOCAL.0 + 1);
+    /// x86: Whether IRQ0 is overridden
+    pub const X86_IRQ0_OVERRIDE: Self = Self(Self::ARCH_LOCAL.0 + 2);
+    /// x86: The E820 memory map
+    pub const X86_E820_TABLE: Self = Self(Self::ARCH_LOCAL.0 + 3);
+    /// x86: The HPET configuration
+    pub const X86_HPET: Self = Self(Self::ARCH_LOCAL.0 + 4);
+
+    /// Return `true` if this key is in the range used by files.
+    pub fn is_file(self) -> bool {
+        self >= Self::FILE_FIRST && self < Self::WRITE_CHANNEL
+    }
+
+    /// Return `true` if this key is specific to an architecture.
+    pub fn is_arch_local(self) -> bool {
+        (self.0 & Self::ARCH_LOCAL.0) != 0
+    }
+}
+
+impl From<u16> for FwCfgKey {
+    fn from(key: u16) -> Self {
+        Self(key)
+    }
+}
+
+impl From<FwCfgKey> for u16 {
+    fn from(key: FwCfgKey) -> Self {
+        key.0
+    }
+}
+
+impl fmt::Debug for FwCfgKey {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "FwCfgKey({:#06x})", self.0)
+    }
+}
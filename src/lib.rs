@@ -4,8 +4,14 @@
 //!
 //! # Supported architectures
 //!
-//! As of today, this crate only supported x86 and x86_64. However, it is possible
-//! to add support for other platforms, such as ARM.
+//! On x86 and x86-64, fw_cfg is accessed through I/O ports with [`FwCfg::take`].
+//! On other architectures, it is accessed through MMIO with
+//! [`FwCfg::take_memory_mapped`], at the address given by the device tree or
+//! ACPI, and [`FwCfgBuilder::layout`] supports other register layouts.
+//!
+//! On the RISC-V and LoongArch64 `virt` machines, `FwCfg::take_virt` uses
+//! the default address of the device, `VIRT_BASE_ADDRESS`.
+//! The crate is tested in QEMU on i686, RISC-V 64 and LoongArch64.
 //!
 //! # Examples
 //! ```
@@ -13,76 +19,218 @@
 //!
 //! // Verify that we are inside QEMU.
 //! if running_in_qemu() {
-//!     // Create a new `FwCfg` instance.
-//!     let fw_cfg = unsafe { FwCfg::new().unwrap() };
+//!     // Take the `FwCfg` instance.
+//!     let mut fw_cfg = unsafe { FwCfg::take().unwrap() };
 //!     // Retrieve information of a file.
-//!     let file = fw_cfg.find_file("etc/igd-opregion").unwrap();
+//!     let file = fw_cfg.find_file("etc/igd-opregion").unwrap().unwrap();
 //!     // Read data from the file.
-//!     let data = fw_cfg.read_file(&file);
+//!     let data = fw_cfg.read_file(&file).unwrap();
 //! }
 //! ```
+//!
+//! See the [`igd`] module for what to do with this file.
 
 #![no_std]
 
 #[cfg(feature = "alloc")]
-#[macro_use]
 extern crate alloc;
 
 #[cfg(feature = "alloc")]
 use alloc::vec::Vec;
 
-use core::cell::UnsafeCell;
-use core::convert::TryFrom;
 use core::fmt;
-use core::mem::size_of;
-use core::sync::atomic::{compiler_fence, Ordering};
+use core::mem::{size_of, MaybeUninit};
+use core::ptr;
+use core::slice;
+use core::str::Utf8Error;
+use core::sync::atomic::{AtomicBool, Ordering};
 
 #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
 #[path = "x86.rs"]
 mod arch;
 
-mod selector_keys {
-    pub const SIGNATURE: u16 = 0x0000;
-    pub const FEATURE_BITMAP: u16 = 0x0001;
-    pub const DIR: u16 = 0x0019;
-}
+mod barrier;
+mod batch;
+mod block;
+mod builder;
+mod directory;
+mod dma;
+mod error;
+mod features;
+mod future;
+pub mod igd;
+mod key;
+mod mmio;
+mod reader;
+mod shared;
+#[cfg(any(
+    target_arch = "riscv32",
+    target_arch = "riscv64",
+    target_arch = "loongarch64"
+))]
+mod virt;
+
+use dma::{DmaBuffer, FwCfgDmaAccess};
+use mmio::MemoryMappedDevice;
+
+pub use batch::DmaBatch;
+pub use block::FwCfgBlockDevice;
+pub use builder::FwCfgBuilder;
+pub use directory::{FwCfgDirEntry, FwCfgDirectory};
+pub use dma::{DmaCache, DmaPoll, PollBudget, VirtToPhys};
+pub use error::Error;
+pub use features::FwCfgFeatures;
+pub use future::DmaFuture;
+pub use key::FwCfgKey;
+pub use mmio::MmioLayout;
+pub use reader::FwCfgReader;
+pub use shared::{SharedFwCfg, SharedFwCfgGuard};
+#[cfg(any(
+    target_arch = "riscv32",
+    target_arch = "riscv64",
+    target_arch = "loongarch64"
+))]
+pub use virt::VIRT_BASE_ADDRESS;
 
 const SIGNATURE_DATA: &[u8] = b"QEMU";
 
-mod feature_bitmasks {
-    pub const _HAS_TRADITIONAL_INTERFACE: u32 = 1 << 0;
-    pub const HAS_DMA: u32 = 1 << 1;
+// https://gitlab.com/qemu-project/qemu/-/blob/v7.0.0/docs/specs/fw_cfg.txt#L170
+const DMA_SIGNATURE_DATA: &[u8; 8] = b"QEMU CFG";
+
+/// The former error type for [`FwCfg`] construction.
+#[deprecated(note = "use `Error` instead")]
+pub type FwCfgError = Error;
+
+/// The former error type for writes and DMA.
+#[deprecated(note = "use `Error` instead")]
+pub type FwCfgWriteError = Error;
+
+/// Limits on data provided by the host through fw_cfg.
+///
+/// The default is [`FwCfgLimits::UNLIMITED`], which trusts the host.
+/// Confidential guests (such as AMD SEV or Intel TDX) should use
+/// [`FwCfgLimits::HARDENED`] or stricter limits instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FwCfgLimits {
+    /// The maximum number of entries in the file directory.
+    pub max_entries: u32,
+    /// The maximum size of an item read into memory, in bytes.
+    pub max_item_size: usize,
+    /// Reject directory entries whose name is empty, not NUL-terminated,
+    /// not valid UTF-8 or contains control characters.
+    pub validate_names: bool,
+    /// Reject directories where the same name appears more than once.
+    ///
+    /// [`FwCfg::iter_files`] only detects adjacent duplicates since QEMU sorts
+    /// the directory by name (see [`FwCfgQuirks::sorted_directory`]), while
+    /// [`FwCfg::find_files`] also detects duplicates of the names it looks up
+    /// anywhere in the directory.
+    pub reject_duplicates: bool,
+}
+
+impl FwCfgLimits {
+    /// Trust everything provided by the host.
+    pub const UNLIMITED: Self = Self {
+        max_entries: u32::MAX,
+        max_item_size: usize::MAX,
+        validate_names: false,
+        reject_duplicates: false,
+    };
+
+    /// Limits suitable for an untrusted host.
+    pub const HARDENED: Self = Self {
+        // File items use selector keys 0x20 up to 0x3fff.
+        max_entries: 0x4000 - 0x20,
+        max_item_size: 64 * 1024 * 1024,
+        validate_names: true,
+        reject_duplicates: true,
+    };
+}
+
+impl Default for FwCfgLimits {
+    fn default() -> Self {
+        Self::UNLIMITED
+    }
+}
+
+/// Differences in behaviour between fw_cfg implementations.
+///
+/// The default is [`FwCfgQuirks::QEMU`]. Other VMMs implementing the same
+/// interface can be supported by building `FwCfg` with different quirks,
+/// for example [`FwCfgQuirks::GENERIC`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FwCfgQuirks {
+    /// The signature expected at [`FwCfgKey::SIGNATURE`],
+    /// or `None` to skip checking it.
+    pub signature: Option<&'static [u8]>,
+    /// Use the DMA interface if the device advertises it.
+    pub dma: bool,
+    /// Check the signature returned by the DMA address register
+    /// before using the DMA interface.
+    pub verify_dma_signature: bool,
+    /// The device sorts the file directory by name.
+    ///
+    /// If not, [`FwCfgDirectory`] sorts its entries after reading them.
+    pub sorted_directory: bool,
+    /// The device resets the DMA address register after each DMA operation,
+    /// so its upper half does not need to be written again when it is zero.
+    pub dma_address_reset: bool,
 }
 
-/// An enum type for [`FwCfg`] errors.
-#[derive(Debug, PartialEq, Eq)]
-#[non_exhaustive]
-pub enum FwCfgError {
-    /// Invalid signature returned from QEMU fw_cfg I/O port
-    InvalidSignature,
+impl FwCfgQuirks {
+    /// The behaviour of QEMU.
+    pub const QEMU: Self = Self {
+        signature: Some(SIGNATURE_DATA),
+        dma: true,
+        verify_dma_signature: true,
+        sorted_directory: true,
+        dma_address_reset: true,
+    };
+
+    /// Assume only what the fw_cfg interface requires.
+    pub const GENERIC: Self = Self {
+        signature: None,
+        dma: true,
+        verify_dma_signature: false,
+        sorted_directory: false,
+        dma_address_reset: false,
+    };
 }
 
-/// An enum type for [`FwCfg::write_file`] errors.
-#[derive(Debug, PartialEq, Eq)]
-#[non_exhaustive]
-pub enum FwCfgWriteError {
-    /// This fw_cfg device does not support DMA access,
-    /// which is necessary for writing since QEMU v2.4.
-    ///
-    /// Note: writing through the data register for older QEMU versions
-    /// is not supported by this crate.
-    DmaNotAvailable,
-    /// Something went wrong during a DMA write
-    DmaFailed,
+impl Default for FwCfgQuirks {
+    fn default() -> Self {
+        Self::QEMU
+    }
 }
 
 /// A struct for accessing QEMU fw_cfg.
 #[derive(Debug)]
 pub struct FwCfg {
     mode: Mode,
-    feature_bitmap: Option<u32>,
+    features: FwCfgFeatures,
+    /// The selected item and the offset in its data,
+    /// or `None` if unknown.
+    position: Option<(FwCfgKey, usize)>,
+    limits: FwCfgLimits,
+    quirks: FwCfgQuirks,
+    dma_buffer: Option<DmaBuffer>,
+    direct_dma: bool,
+    legacy_writes: bool,
+    virt_to_phys: Option<&'static (dyn VirtToPhys + Sync)>,
+    dma_poll: Option<&'static (dyn DmaPoll + Sync)>,
+    dma_cache: Option<&'static (dyn DmaCache + Sync)>,
+    /// The buffer staging a DMA operation that timed out
+    /// and may still be in progress.
+    dma_pending: Option<DmaBuffer>,
 }
 
+// SAFETY: The device itself is a global resource, and the pointers to the
+// DMA buffer are only used while accessing it through `&mut FwCfg`.
+unsafe impl Send for FwCfg {}
+
+/// Whether a `FwCfg` value exists.
+static TAKEN: AtomicBool = AtomicBool::new(false);
+
 #[derive(Debug)]
 enum Mode {
     #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
@@ -91,75 +239,231 @@ enum Mode {
 }
 
 impl FwCfg {
-    /// Build `FwCfg` for the x86/x86-64 I/O port.
+    /// Take the fw_cfg device at the x86/x86-64 I/O port.
+    ///
+    /// Returns [`Error::AlreadyTaken`] if another `FwCfg` value still exists,
+    /// however it was built. The device is released when that value is
+    /// dropped, unless a DMA operation that timed out is still in progress
+    /// (see [`Error::Timeout`]). Only one `FwCfg` value may exist at the same time since it
+    /// accesses a global shared stateful resource, and since it tracks the
+    /// selected item and the offset in its data: reads would start at the
+    /// wrong offset if anything else accessed the device in between.
+    ///
+    /// The signature is verified before anything else is read
+    /// from the device. See [`FwCfgBuilder`] for devices with other quirks.
     ///
     /// # Safety
     ///
     /// This may only be called when running inside QEMU
     /// since I/O ports are accessed without additional checks.
     ///
-    /// Only one `FwCfg` value may exist at the same time
-    /// since it accesses a global shared stateful resource.
+    /// This is why taking the device is not safe, even though the crate
+    /// makes sure that it is only taken once.
     #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
-    pub unsafe fn new_for_x86() -> Result<FwCfg, FwCfgError> {
-        Self::new_for_mode(Mode::IOPort)
+    pub unsafe fn take() -> Result<FwCfg, Error> {
+        FwCfgBuilder::new().take_x86()
     }
 
-    /// Build `FwCfg` for the device memory-mapped at the give base pointer.
+    /// Take the fw_cfg device memory-mapped at the given base pointer.
+    ///
+    /// The registers are expected at the offsets of [`MmioLayout::VIRT`],
+    /// see [`FwCfgBuilder::layout`] for other machines.
+    ///
+    /// Returns [`Error::AlreadyTaken`] if another `FwCfg` value still exists,
+    /// as explained in [`FwCfg::take`].
     ///
     /// # Safety
     ///
     /// The pointer must point to a valid fw_cfg device.
+    pub unsafe fn take_memory_mapped(base_ptr: *mut ()) -> Result<FwCfg, Error> {
+        FwCfgBuilder::new().take_memory_mapped(base_ptr)
+    }
+
+    pub(crate) fn take_with(new: impl FnOnce() -> Result<FwCfg, Error>) -> Result<FwCfg, Error> {
+        if TAKEN
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            return Err(Error::AlreadyTaken);
+        }
+        let result = new();
+        if result.is_err() {
+            TAKEN.store(false, Ordering::Release);
+        }
+        result
+    }
+
+    /// Build `FwCfg` for the x86/x86-64 I/O port.
+    ///
+    /// This now takes the device like [`FwCfg::take`].
+    ///
+    /// # Safety
     ///
-    /// Only one `FwCfg` value may exist at the same time for that pointer.
-    pub unsafe fn new_memory_mapped(base_ptr: *mut ()) -> Result<FwCfg, FwCfgError> {
-        let device = MemoryMappedDevice::new(base_ptr);
-        Self::new_for_mode(Mode::MemoryMapped(device))
+    /// See [`FwCfg::take`].
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    #[deprecated(note = "use `FwCfg::take` instead")]
+    pub unsafe fn new_for_x86() -> Result<FwCfg, Error> {
+        Self::take()
+    }
+
+    /// Build `FwCfg` for the device memory-mapped at the give base pointer.
+    ///
+    /// This now takes the device like [`FwCfg::take_memory_mapped`].
+    ///
+    /// # Safety
+    ///
+    /// See [`FwCfg::take_memory_mapped`].
+    #[deprecated(note = "use `FwCfg::take_memory_mapped` instead")]
+    pub unsafe fn new_memory_mapped(base_ptr: *mut ()) -> Result<FwCfg, Error> {
+        Self::take_memory_mapped(base_ptr)
     }
 
-    unsafe fn new_for_mode(mode: Mode) -> Result<FwCfg, FwCfgError> {
+    pub(crate) unsafe fn new_for_mode(mode: Mode, quirks: FwCfgQuirks) -> Result<FwCfg, Error> {
         let mut fw_cfg = FwCfg {
             mode,
-            feature_bitmap: None,
+            features: FwCfgFeatures::empty(),
+            position: None,
+            limits: FwCfgLimits::default(),
+            quirks,
+            dma_buffer: None,
+            direct_dma: true,
+            legacy_writes: false,
+            virt_to_phys: None,
+            dma_poll: None,
+            dma_cache: None,
+            dma_pending: None,
         };
 
-        let mut signature = [0u8; SIGNATURE_DATA.len()];
-        fw_cfg.select(selector_keys::SIGNATURE);
-        fw_cfg.read(&mut signature);
+        if let Some(expected) = quirks.signature {
+            fw_cfg.select(FwCfgKey::SIGNATURE);
+            let mut signature = [0u8; 8];
+            for chunk in expected.chunks(signature.len()) {
+                let signature = &mut signature[..chunk.len()];
+                fw_cfg.read(signature);
+                if signature != chunk {
+                    return Err(Error::InvalidSignature);
+                }
+            }
+        }
+
+        let mut features = [0u8; 4];
+        fw_cfg.select(FwCfgKey::ID);
+        fw_cfg.read(&mut features);
+        fw_cfg.features = FwCfgFeatures::from_bits_retain(u32::from_le_bytes(features));
 
-        if signature != SIGNATURE_DATA {
-            return Err(FwCfgError::InvalidSignature);
+        if fw_cfg.has_dma()
+            && quirks.verify_dma_signature
+            && fw_cfg.read_dma_signature() != u64::from_be_bytes(*DMA_SIGNATURE_DATA)
+        {
+            return Err(Error::InvalidDmaSignature);
         }
 
         Ok(fw_cfg)
     }
 
-    /// Return the "feature" configuration item,
-    /// reading it from the device if necessary and caching it.
-    fn feature_bitmap(&mut self) -> u32 {
-        self.feature_bitmap.unwrap_or_else(|| {
-            let mut buffer = [0u8; 4];
-            self.select(selector_keys::FEATURE_BITMAP);
-            self.read(&mut buffer);
-            let value = u32::from_le_bytes(buffer);
-            self.feature_bitmap = Some(value);
-            value
-        })
+    /// Return the features supported by the device.
+    ///
+    /// If [`FwCfgFeatures::DMA`] is set, the DMA interface has been verified
+    /// to return its signature when the `FwCfg` value was built, unless
+    /// disabled by [`FwCfgQuirks::verify_dma_signature`]. It is only used
+    /// if [`FwCfgQuirks::dma`] is set.
+    pub fn features(&self) -> FwCfgFeatures {
+        self.features
     }
 
-    /// Return an iterator of all files in the fw_cfg directory
-    pub fn iter_files(&mut self) -> impl Iterator<Item = FwCfgFile> + '_ {
-        self.select(selector_keys::DIR);
+    /// Return the quirks this `FwCfg` value was built with.
+    pub fn quirks(&self) -> FwCfgQuirks {
+        self.quirks
+    }
 
-        let count = {
+    /// Return the limits applied to data provided by the host.
+    pub fn limits(&self) -> FwCfgLimits {
+        self.limits
+    }
+
+    /// Set the limits applied to data provided by the host.
+    pub fn set_limits(&mut self, limits: FwCfgLimits) {
+        self.limits = limits;
+    }
+
+    /// Return an iterator of all files in the fw_cfg directory.
+    ///
+    /// Entries violating the configured [`FwCfgLimits`] are returned as errors.
+    /// If the directory has too many entries, or a DMA operation that timed
+    /// out is still in progress, only one error is returned.
+    pub fn iter_files(&mut self) -> impl Iterator<Item = Result<FwCfgFile, Error>> + '_ {
+        let mut error = self.check_dma_pending().err();
+        let count = if error.is_none() {
+            self.select(FwCfgKey::FILE_DIR);
             let mut buf = [0u8; size_of::<u32>()];
             self.read(&mut buf);
             u32::from_be_bytes(buf)
+        } else {
+            0
         };
-        (0..count).map(move |_| {
+        let limits = self.limits;
+        let mut remaining = count;
+        if count > limits.max_entries {
+            error = Some(Error::TooManyEntries);
+        }
+        let mut previous: Option<FwCfgFile> = None;
+        core::iter::from_fn(move || {
+            if let Some(error) = error.take() {
+                remaining = 0;
+                return Some(Err(error));
+            }
+            if remaining == 0 {
+                return None;
+            }
+            remaining -= 1;
+
             let mut file = FwCfgFile::default();
             self.read(file.as_mut_bytes());
-            file
+
+            if limits.validate_names && !file.has_valid_name() {
+                return Some(Err(Error::InvalidName));
+            }
+            if limits.reject_duplicates {
+                let duplicate = previous
+                    .as_ref()
+                    .is_some_and(|previous| previous.name_bytes() == file.name_bytes());
+                previous = Some(file.clone());
+                if duplicate {
+                    return Some(Err(Error::DuplicateName));
+                }
+            }
+            Some(Ok(file))
+        })
+    }
+
+    /// Return an iterator of the files whose name starts with `prefix`,
+    /// such as `"opt/com.example/"`.
+    ///
+    /// Errors are returned the same as [`FwCfg::iter_files`].
+    pub fn iter_files_with_prefix<'a>(
+        &'a mut self,
+        prefix: &'a str,
+    ) -> impl Iterator<Item = Result<FwCfgFile, Error>> + 'a {
+        self.iter_files().filter(move |file| match file {
+            Ok(file) => file.name_bytes().starts_with(prefix.as_bytes()),
+            Err(_) => true,
+        })
+    }
+
+    /// Return an iterator of the files whose name matches the glob `pattern`,
+    /// such as `"genroms/*.bin"`.
+    ///
+    /// `*` matches any number of bytes and `?` matches a single byte,
+    /// both except `/`. Other bytes match themselves.
+    ///
+    /// Errors are returned the same as [`FwCfg::iter_files`].
+    pub fn iter_files_matching<'a>(
+        &'a mut self,
+        pattern: &'a str,
+    ) -> impl Iterator<Item = Result<FwCfgFile, Error>> + 'a {
+        self.iter_files().filter(move |file| match file {
+            Ok(file) => directory::glob_match(pattern.as_bytes(), file.name_bytes()),
+            Err(_) => true,
         })
     }
 
@@ -170,6 +474,13 @@ impl FwCfg {
     /// replacing the value in `Option<FwCfgFile>` of the corresponding tuple,
     /// otherwise it will retained the same value as before.
     ///
+    /// Names are compared byte-wise, so directory entries whose names are not
+    /// valid UTF-8 are never matched instead of causing a panic.
+    ///
+    /// If the configured [`FwCfgLimits`] reject duplicates, the entire
+    /// directory is scanned and an error is returned if a name is found
+    /// again with a different key.
+    ///
     /// # Examples
     /// ```
     /// use qemu_fw_cfg::FwCfg;
@@ -179,23 +490,34 @@ impl FwCfg {
     ///     ("etc/igd-opregion", None),
     ///     ("opt/another/file.txt", None),
     /// ];
-    /// fw_cfg.find_files(&mut files);
+    /// fw_cfg.find_files(&mut files).unwrap();
     /// ```
-    pub fn find_files(&mut self, entries: &mut [(&str, Option<FwCfgFile>)]) {
+    pub fn find_files(&mut self, entries: &mut [(&str, Option<FwCfgFile>)]) -> Result<(), Error> {
+        let reject_duplicates = self.limits.reject_duplicates;
+
         for file in self.iter_files() {
+            let file = file?;
             let mut changed = false;
 
             for (name, ret) in entries.iter_mut() {
-                if file.name() == *name {
+                if file.name_bytes() == name.as_bytes() {
+                    if let Some(previous) = ret {
+                        let same_name = previous.name_bytes() == file.name_bytes();
+                        if reject_duplicates && same_name && previous.key() != file.key() {
+                            return Err(Error::DuplicateName);
+                        }
+                    }
                     *ret = Some(file.clone());
                     changed = true;
                 }
             }
 
-            if changed && entries.iter().all(|entry| entry.1.is_some()) {
-                return;
+            if !reject_duplicates && changed && entries.iter().all(|entry| entry.1.is_some()) {
+                return Ok(());
             }
         }
+
+        Ok(())
     }
 
     /// Find a single file by its name. Returns `None` if the file is missing.
@@ -205,79 +527,254 @@ impl FwCfg {
     /// use qemu_fw_cfg::FwCfg;
     ///
     /// let fw_cfg = unsafe { FwCfg::new().unwrap() };
-    /// let file = fw_cfg.find_file("etc/igd-opregion").unwrap();
+    /// let file = fw_cfg.find_file("etc/igd-opregion").unwrap().unwrap();
     /// ```
-    pub fn find_file(&mut self, name: &str) -> Option<FwCfgFile> {
+    pub fn find_file(&mut self, name: &str) -> Result<Option<FwCfgFile>, Error> {
         let mut entries = [(name, None)];
-        self.find_files(&mut entries);
-        entries[0].1.take()
+        self.find_files(&mut entries)?;
+        Ok(entries[0].1.take())
     }
 
     /// Read a file and fill its data in `buffer`.
     ///
     /// If the size of `buffer` is greater or equals to the size of the file,
     /// then it will fill the entire data in `buffer[0..file.size()]`, otherwise
-    /// it will only fill up to `buffer.len()`.
-    pub fn read_file_to_buffer(&mut self, file: &FwCfgFile, buffer: &mut [u8]) {
+    /// it will only fill up to `buffer.len()`, as when reading a header.
+    /// A shorter buffer is not an error: this returns the number of bytes
+    /// copied, which is less than [`FwCfgFile::size`] if the file was truncated.
+    pub fn read_file_to_buffer(
+        &mut self,
+        file: &FwCfgFile,
+        buffer: &mut [u8],
+    ) -> Result<usize, Error> {
         let len = file.size().min(buffer.len());
-        self.select(file.key());
-        self.read(&mut buffer[..len]);
+        unsafe { self.read_item(file.key(), buffer.as_mut_ptr(), len)? };
+        Ok(len)
+    }
+
+    /// Read a file into `buffer`, which may be uninitialized,
+    /// and return the part of `buffer` filled with its data.
+    ///
+    /// Like [`FwCfg::read_file_to_buffer`], this fills up to `buffer.len()`
+    /// bytes, but without having to initialize `buffer` first. The file was
+    /// truncated if the returned slice is shorter than [`FwCfgFile::size`].
+    pub fn read_file_to_uninit<'a>(
+        &mut self,
+        file: &FwCfgFile,
+        buffer: &'a mut [MaybeUninit<u8>],
+    ) -> Result<&'a mut [u8], Error> {
+        let len = file.size().min(buffer.len());
+        let data = buffer.as_mut_ptr().cast::<u8>();
+        unsafe { self.read_item(file.key(), data, len)? };
+        Ok(unsafe { slice::from_raw_parts_mut(data, len) })
     }
 
     /// Read a file and return the data in `Vec<u8>`.
+    ///
+    /// Returns an error if the file is larger than [`FwCfgLimits::max_item_size`].
+    #[cfg(feature = "alloc")]
+    pub fn read_file(&mut self, file: &FwCfgFile) -> Result<Vec<u8>, Error> {
+        if file.size() > self.limits.max_item_size {
+            return Err(Error::ItemTooLarge);
+        }
+        self.read_file_to_vec(file, Vec::with_capacity(file.size()))
+    }
+
+    /// Like [`FwCfg::read_file`], but returns [`Error::OutOfMemory`]
+    /// instead of aborting when memory for the data cannot be allocated.
+    #[cfg(feature = "alloc")]
+    pub fn try_read_file(&mut self, file: &FwCfgFile) -> Result<Vec<u8>, Error> {
+        if file.size() > self.limits.max_item_size {
+            return Err(Error::ItemTooLarge);
+        }
+        let mut buf = Vec::new();
+        buf.try_reserve_exact(file.size())
+            .map_err(|_| Error::OutOfMemory)?;
+        self.read_file_to_vec(file, buf)
+    }
+
+    /// Read a file into the spare capacity of `buf`, which must fit it.
     #[cfg(feature = "alloc")]
-    pub fn read_file(&mut self, file: &FwCfgFile) -> Vec<u8> {
-        let mut buf = vec![0u8; file.size()];
-        self.select(file.key());
-        self.read(&mut buf);
-        buf
+    fn read_file_to_vec(&mut self, file: &FwCfgFile, mut buf: Vec<u8>) -> Result<Vec<u8>, Error> {
+        let len = file.size();
+        assert!(buf.capacity() >= len);
+        unsafe {
+            self.read_item(file.key(), buf.as_mut_ptr(), len)?;
+            buf.set_len(len);
+        }
+        Ok(buf)
+    }
+
+    /// Select an item by its key and read its data into `buffer`, from the start.
+    ///
+    /// This gives access to items that are not files, such as
+    /// [`FwCfgKey::X86_E820_TABLE`]. Bytes past the end of the item
+    /// are filled with zeros by QEMU.
+    pub fn read_key(&mut self, key: FwCfgKey, buffer: &mut [u8]) -> Result<(), Error> {
+        unsafe { self.read_item(key, buffer.as_mut_ptr(), buffer.len()) }
     }
 
     /// Write provided `data` into a file, starting at file offset 0.
     ///
-    /// This requires the DMA interface, which QEMU supports since version 2.9.
-    pub fn write_to_file(&mut self, file: &FwCfgFile, data: &[u8]) -> Result<(), FwCfgWriteError> {
-        let has_dma = (self.feature_bitmap() & feature_bitmasks::HAS_DMA) != 0;
-        if !has_dma {
-            return Err(FwCfgWriteError::DmaNotAvailable);
+    /// This requires the DMA interface, which QEMU supports since version 2.9,
+    /// unless legacy writes are enabled with [`FwCfg::set_legacy_writes`].
+    pub fn write_to_file(&mut self, file: &FwCfgFile, data: &[u8]) -> Result<(), Error> {
+        self.check_dma_pending()?;
+        if self.legacy_writes && !self.has_dma() {
+            self.select(FwCfgKey(file.key().0 | FwCfgKey::WRITE_CHANNEL.0));
+            self.write(data);
+            return Ok(());
         }
-        let control = (file.key() as u32) << 16 | FwCfgDmaAccess::WRITE | FwCfgDmaAccess::SELECT;
-        let access = FwCfgDmaAccess::new(control, data.as_ptr() as _, data.len());
-        // `data` and `access` initialization must not be reordered to after this:
-        compiler_fence(Ordering::Release);
-        match &mut self.mode {
-            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
-            Mode::IOPort => unsafe { arch::start_dma(&access) },
-            Mode::MemoryMapped(device) => device.start_dma(&access),
+        let control = FwCfgDmaAccess::select(file.key()) | FwCfgDmaAccess::WRITE;
+        self.dma(control, data.as_ptr() as *mut u8, data.len())
+    }
+
+    /// Enable or disable writing through the data register
+    /// when the device does not support DMA.
+    ///
+    /// This is disabled by default. It is needed for writing on QEMU versions
+    /// before v2.4, but QEMU v2.4 to v2.8 silently ignore these writes.
+    /// Writes through the data register cannot fail, so
+    /// [`FwCfg::write_to_file`] cannot detect whether they had any effect.
+    pub fn set_legacy_writes(&mut self, enabled: bool) {
+        self.legacy_writes = enabled;
+    }
+
+    /// Select an item and read `len` bytes of its data from the start into
+    /// `data`, which may be uninitialized, through the DMA buffer if one is
+    /// registered.
+    unsafe fn read_item(&mut self, key: FwCfgKey, data: *mut u8, len: usize) -> Result<(), Error> {
+        self.read_item_at(key, 0, data, len)
+    }
+
+    /// Like [`FwCfg::read_item`], but starting at `offset` in the data.
+    ///
+    /// Errors of the device during DMA are returned rather than
+    /// falling back to the data register.
+    unsafe fn read_item_at(
+        &mut self,
+        key: FwCfgKey,
+        offset: usize,
+        data: *mut u8,
+        len: usize,
+    ) -> Result<(), Error> {
+        // The device may still be running a DMA operation that timed out.
+        self.check_dma_pending()?;
+        self.seek(key, offset)?;
+        if self.dma_buffer.is_some() && self.has_dma() {
+            return self.dma(FwCfgDmaAccess::READ, data, len);
         }
-        loop {
-            let control = access.read_control();
-            if (control & FwCfgDmaAccess::ERROR) != 0 {
-                return Err(FwCfgWriteError::DmaFailed);
+        self.read_raw(data, len);
+        Ok(())
+    }
+
+    /// Move to `offset` in the data of an item, selecting it only if
+    /// necessary and skipping only the bytes from the current offset.
+    ///
+    /// Skipping falls back to the data register if DMA is unavailable or
+    /// fails, but not if the device may still be running it.
+    fn seek(&mut self, key: FwCfgKey, offset: usize) -> Result<(), Error> {
+        let mut current = match self.position {
+            Some((selected, current)) if selected == key && current <= offset => current,
+            _ => {
+                self.select(key);
+                0
             }
-            if control == 0 {
-                return Ok(());
+        };
+        if current == offset {
+            return Ok(());
+        }
+        if self.has_dma() {
+            match self.dma(FwCfgDmaAccess::SKIP, ptr::null_mut(), offset - current) {
+                Ok(()) => return Ok(()),
+                Err(error @ (Error::Timeout | Error::DmaPending)) => return Err(error),
+                Err(_) => {}
+            }
+            if self.position != Some((key, current)) {
+                self.select(key);
+                current = 0;
             }
         }
+        let mut discarded = [0u8; 64];
+        while current < offset {
+            let len = (offset - current).min(discarded.len());
+            self.read(&mut discarded[..len]);
+            current += len;
+        }
+        Ok(())
     }
 
-    fn select(&mut self, key: u16) {
+    fn select(&mut self, key: FwCfgKey) {
+        // Selecting an item again would only rewind it to the start.
+        if self.position == Some((key, 0)) {
+            return;
+        }
         match &mut self.mode {
             #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
-            Mode::IOPort => unsafe { arch::write_selector(key) },
-            Mode::MemoryMapped(device) => device.write_selector(key),
+            Mode::IOPort => unsafe { arch::write_selector(key.0) },
+            Mode::MemoryMapped(device) => device.write_selector(key.0),
+        }
+        self.position = Some((key, 0));
+    }
+
+    /// Update the position after an access with `control`
+    /// (as in a DMA descriptor) on `len` bytes.
+    fn advance_position(&mut self, control: u32, len: usize) {
+        if (control & FwCfgDmaAccess::SELECT) != 0 {
+            self.position = Some((FwCfgKey((control >> 16) as u16), 0));
+        }
+        let transfer = FwCfgDmaAccess::READ | FwCfgDmaAccess::WRITE | FwCfgDmaAccess::SKIP;
+        if (control & transfer) != 0 {
+            if let Some((_, offset)) = &mut self.position {
+                *offset = offset.saturating_add(len);
+            }
         }
     }
 
     fn read(&mut self, buffer: &mut [u8]) {
+        unsafe { self.read_raw(buffer.as_mut_ptr(), buffer.len()) }
+    }
+
+    /// Read `len` bytes into `data`, which may be uninitialized.
+    unsafe fn read_raw(&mut self, data: *mut u8, len: usize) {
+        match &mut self.mode {
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Mode::IOPort => arch::read_data(data, len),
+            Mode::MemoryMapped(device) => device.read_data(data, len),
+        }
+        self.advance_position(FwCfgDmaAccess::READ, len);
+    }
+
+    fn write(&mut self, data: &[u8]) {
+        match &mut self.mode {
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Mode::IOPort => unsafe { arch::write_data(data) },
+            Mode::MemoryMapped(device) => device.write_data(data),
+        }
+        self.advance_position(FwCfgDmaAccess::WRITE, data.len());
+    }
+
+    fn read_dma_signature(&mut self) -> u64 {
         match &mut self.mode {
             #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
-            Mode::IOPort => unsafe { arch::read_data(buffer) },
-            Mode::MemoryMapped(device) => device.read_data(buffer),
+            Mode::IOPort => unsafe { arch::read_dma_signature() },
+            Mode::MemoryMapped(device) => device.read_dma_signature(),
         }
     }
 }
 
+impl Drop for FwCfg {
+    fn drop(&mut self) {
+        // The device may still access the buffer staging a DMA operation that
+        // timed out, which may be owned by this crate. Keep the device taken
+        // forever rather than waiting for a device that may never complete it.
+        if self.check_dma_pending().is_err() {
+            return;
+        }
+        TAKEN.store(false, Ordering::Release);
+    }
+}
+
 const _: () = assert!(size_of::<FwCfgFile>() == 64);
 
 /// A struct that contains information of a fw_cfg file.
@@ -312,14 +809,36 @@ impl FwCfgFile {
         u32::from_be(self.size_be) as usize
     }
 
-    fn key(&self) -> u16 {
-        u16::from_be(self.key_be)
+    /// The selector key of this file.
+    pub fn key(&self) -> FwCfgKey {
+        FwCfgKey(u16::from_be(self.key_be))
+    }
+
+    /// The name of this file as raw bytes, up to the first NUL byte.
+    pub fn name_bytes(&self) -> &[u8] {
+        self.name_bytes.split(|&b| b == b'\x00').next().unwrap()
+    }
+
+    /// The name of this file, or an error if it is not valid UTF-8.
+    pub fn try_name(&self) -> Result<&str, Utf8Error> {
+        core::str::from_utf8(self.name_bytes())
     }
 
     /// The name of this file.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the name is not valid UTF-8. The host controls file names,
+    /// so prefer [`FwCfgFile::try_name`] or [`FwCfgFile::name_bytes`]
+    /// when it is not trusted.
     pub fn name(&self) -> &str {
-        let bytes = self.name_bytes.split(|&b| b == b'\x00').next().unwrap();
-        core::str::from_utf8(bytes).unwrap()
+        self.try_name().unwrap()
+    }
+
+    fn has_valid_name(&self) -> bool {
+        let terminated = self.name_bytes.contains(&b'\x00');
+        let name = self.try_name().unwrap_or("");
+        terminated && !name.is_empty() && !name.chars().any(char::is_control)
     }
 
     fn as_mut_bytes(&mut self) -> &mut [u8; size_of::<Self>()] {
@@ -331,96 +850,12 @@ impl FwCfgFile {
 
 impl fmt::Debug for FwCfgFile {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        fmt.debug_struct("FwCfgFile")
-            .field("key", &self.key())
-            .field("size", &self.size())
-            .field("name", &self.name())
-            .finish()
-    }
-}
-
-#[derive(Debug)]
-struct MemoryMappedDevice {
-    base_ptr: *mut (),
-}
-
-impl MemoryMappedDevice {
-    unsafe fn new(base_ptr: *mut ()) -> Self {
-        Self { base_ptr }
-    }
-
-    fn register<T>(&self, offset_in_bytes: usize) -> *mut T {
-        let offset = offset_in_bytes / size_of::<T>();
-        unsafe { self.base_ptr.cast::<T>().add(offset) }
-    }
-
-    fn write_selector(&mut self, key: u16) {
-        // https://gitlab.com/qemu-project/qemu/-/blob/v7.0.0/docs/specs/fw_cfg.txt#L87
-        let selector_offset = 8;
-        let selector_ptr = self.register::<u16>(selector_offset);
-        unsafe { selector_ptr.write_volatile(key.to_be()) }
-    }
-
-    fn read_data(&mut self, data: &mut [u8]) {
-        // https://gitlab.com/qemu-project/qemu/-/blob/v7.0.0/docs/specs/fw_cfg.txt#L88
-        let data_offset = 0;
-        let data_ptr = self.register::<usize>(data_offset);
-        for chunk in data.chunks_mut(size_of::<usize>()) {
-            let word = unsafe { data_ptr.read_volatile() };
-            // https://gitlab.com/qemu-project/qemu/-/blob/v7.0.0/docs/specs/fw_cfg.txt#L53
-            // "string-preserving" means native-endian
-            let bytes = word.to_ne_bytes();
-            chunk.copy_from_slice(&bytes[..chunk.len()]);
-        }
-    }
-
-    fn start_dma(&self, access: &FwCfgDmaAccess) {
-        let address = access as *const FwCfgDmaAccess as u64;
-        // https://gitlab.com/qemu-project/qemu/-/blob/v7.0.0/docs/specs/fw_cfg.txt#L89
-        let offset = 16;
-        let dma_address_register: *mut u32 = self.register(offset);
-        unsafe {
-            // https://gitlab.com/qemu-project/qemu/-/blob/v7.0.0/docs/specs/fw_cfg.txt#L167
-            // The DMA address register is 64-bit and big-endian.
-            // Writing its lower half is what triggers DMA,
-            // so write these half separately to control their order:
-            let register_high = dma_address_register;
-            let register_low = dma_address_register.add(1); // One u32
-            let address_high = (address >> 32) as u32;
-            let address_low = address as u32;
-            register_high.write_volatile(address_high.to_be());
-            compiler_fence(Ordering::AcqRel);
-            register_low.write_volatile(address_low.to_be());
-        }
-    }
-}
-
-#[derive(Debug)]
-// NOTE: The memory layout of this struct must match this exactly:
-// https://gitlab.com/qemu-project/qemu/-/blob/v7.0.0/docs/specs/fw_cfg.txt#L177-181
-#[repr(C)]
-struct FwCfgDmaAccess {
-    control_be: UnsafeCell<u32>,
-    length_be: u32,
-    address_be: u64,
-}
-
-impl FwCfgDmaAccess {
-    const ERROR: u32 = 1 << 0;
-    const _READ: u32 = 1 << 1;
-    const _SKIP: u32 = 1 << 2;
-    const SELECT: u32 = 1 << 3;
-    const WRITE: u32 = 1 << 4;
-
-    fn new(control: u32, ptr: *mut (), length: usize) -> Self {
-        Self {
-            control_be: UnsafeCell::new(control.to_be()),
-            length_be: u32::try_from(length).unwrap().to_be(),
-            address_be: u64::try_from(ptr as usize).unwrap().to_be(),
-        }
-    }
-
-    fn read_control(&self) -> u32 {
-        u32::from_be(unsafe { self.control_be.get().read_volatile() })
+        let mut debug = fmt.debug_struct("FwCfgFile");
+        debug.field("key", &self.key()).field("size", &self.size());
+        match self.try_name() {
+            Ok(name) => debug.field("name", &name),
+            Err(_) => debug.field("name", &self.name_bytes()),
+        };
+        debug.finish()
     }
 }
@@ -0,0 +1,215 @@
+use crate::Error;
+use core::mem::size_of;
+use core::sync::atomic::{compiler_fence, Ordering};
+
+/// The layout of the registers of a memory-mapped fw_cfg device.
+///
+/// Offsets are in bytes from the base pointer of the device, see
+/// [`FwCfgBuilder::layout`](crate::FwCfgBuilder::layout).
+/// The default is [`MmioLayout::VIRT`].
+///
+/// Building `FwCfg` returns [`Error::InvalidLayout`] if the data width is
+/// not supported, or if a register is not aligned to the width of its
+/// accesses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MmioLayout {
+    /// The offset of the 16-bit selector register.
+    pub selector_offset: usize,
+    /// Whether the selector register is big-endian.
+    pub selector_big_endian: bool,
+    /// The offset of the data register.
+    pub data_offset: usize,
+    /// The widest access to the data register in bytes, one of 1, 2, 4 or 8.
+    ///
+    /// Accesses are never wider than `usize`.
+    pub data_width: usize,
+    /// The offset of the 64-bit big-endian DMA address register,
+    /// or `None` if the device has no DMA interface.
+    pub dma_offset: Option<usize>,
+}
+
+impl MmioLayout {
+    /// The layout used by the `virt` machines of ARM, RISC-V and LoongArch64.
+    // https://gitlab.com/qemu-project/qemu/-/blob/v7.0.0/docs/specs/fw_cfg.txt#L86-89
+    pub const VIRT: Self = Self {
+        selector_offset: 8,
+        selector_big_endian: true,
+        data_offset: 0,
+        data_width: 8,
+        dma_offset: Some(16),
+    };
+
+    /// The layout used by the PowerPC `mac99` and `g3beige` machines.
+    // https://gitlab.com/qemu-project/qemu/-/blob/v7.0.0/hw/ppc/mac_newworld.c#L108
+    pub const MAC99: Self = Self {
+        selector_offset: 0,
+        selector_big_endian: true,
+        data_offset: 2,
+        data_width: 1,
+        dma_offset: None,
+    };
+
+    /// The layout used by the SPARC `sun4m` machines.
+    // https://gitlab.com/qemu-project/qemu/-/blob/v7.0.0/hw/sparc/sun4m.c#L104
+    pub const SUN4M: Self = Self::MAC99;
+
+    /// The layout of the I/O port interface, when I/O space is memory-mapped,
+    /// such as on the SPARC `sun4u` machine.
+    ///
+    /// The base pointer is where port 0x510 is mapped.
+    // https://gitlab.com/qemu-project/qemu/-/blob/v7.0.0/docs/specs/fw_cfg.txt#L79-82
+    pub const IO_PORT: Self = Self {
+        selector_offset: 0,
+        selector_big_endian: false,
+        data_offset: 1,
+        data_width: 1,
+        dma_offset: Some(4),
+    };
+}
+
+impl Default for MmioLayout {
+    fn default() -> Self {
+        Self::VIRT
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct MemoryMappedDevice {
+    base_ptr: *mut (),
+    layout: MmioLayout,
+}
+
+impl MemoryMappedDevice {
+    pub(crate) unsafe fn new(base_ptr: *mut (), layout: MmioLayout) -> Result<Self, Error> {
+        let device = Self { base_ptr, layout };
+        if !matches!(layout.data_width, 1 | 2 | 4 | 8) {
+            return Err(Error::InvalidLayout);
+        }
+        let aligned =
+            |offset: usize, align: usize| device.register::<u8>(offset) as usize % align == 0;
+        let dma_aligned = layout
+            .dma_offset
+            .map_or(true, |offset| aligned(offset, size_of::<u32>()));
+        if !aligned(layout.selector_offset, size_of::<u16>())
+            || !aligned(layout.data_offset, device.data_width())
+            || !dma_aligned
+        {
+            return Err(Error::InvalidLayout);
+        }
+        Ok(device)
+    }
+
+    pub(crate) fn has_dma(&self) -> bool {
+        self.layout.dma_offset.is_some()
+    }
+
+    fn register<T>(&self, offset_in_bytes: usize) -> *mut T {
+        unsafe { self.base_ptr.cast::<u8>().add(offset_in_bytes).cast() }
+    }
+
+    /// The width of accesses to the data register.
+    fn data_width(&self) -> usize {
+        self.layout.data_width.min(size_of::<usize>())
+    }
+
+    fn dma_offset(&self) -> usize {
+        self.layout
+            .dma_offset
+            .expect("fw_cfg device has no DMA interface")
+    }
+
+    pub(crate) fn write_selector(&mut self, key: u16) {
+        let selector_ptr = self.register::<u16>(self.layout.selector_offset);
+        let key = if self.layout.selector_big_endian {
+            key.to_be()
+        } else {
+            key.to_le()
+        };
+        unsafe { selector_ptr.write_volatile(key) }
+    }
+
+    /// Read `len` bytes into `data`, which may be uninitialized.
+    pub(crate) unsafe fn read_data(&mut self, data: *mut u8, len: usize) {
+        let width = self.data_width();
+        let mut done = 0;
+        while len - done >= width {
+            let chunk = data.add(done);
+            // https://gitlab.com/qemu-project/qemu/-/blob/v7.0.0/docs/specs/fw_cfg.txt#L53
+            // "string-preserving" means native-endian
+            match width {
+                8 => chunk
+                    .cast::<[u8; 8]>()
+                    .write(self.read_word::<u64>().to_ne_bytes()),
+                4 => chunk
+                    .cast::<[u8; 4]>()
+                    .write(self.read_word::<u32>().to_ne_bytes()),
+                2 => chunk
+                    .cast::<[u8; 2]>()
+                    .write(self.read_word::<u16>().to_ne_bytes()),
+                1 => chunk.write(self.read_word::<u8>()),
+                _ => unreachable!("validated in `MemoryMappedDevice::new`"),
+            }
+            done += width;
+        }
+        // Each access advances the data offset by its width, so reading a
+        // whole word for the remainder would skip bytes of the next read.
+        while done < len {
+            data.add(done).write(self.read_word::<u8>());
+            done += 1;
+        }
+    }
+
+    unsafe fn read_word<T>(&self) -> T {
+        self.register::<T>(self.layout.data_offset).read_volatile()
+    }
+
+    pub(crate) fn write_data(&mut self, data: &[u8]) {
+        let data_ptr = self.register::<u8>(self.layout.data_offset);
+        for &byte in data {
+            unsafe { data_ptr.write_volatile(byte) }
+        }
+    }
+
+    pub(crate) fn read_dma_signature(&self) -> u64 {
+        // https://gitlab.com/qemu-project/qemu/-/blob/v7.0.0/docs/specs/fw_cfg.txt#L170
+        let offset = self.dma_offset();
+        #[cfg(target_pointer_width = "64")]
+        if self.register::<u8>(offset) as usize % size_of::<u64>() == 0 {
+            let dma_address_register: *mut u64 = self.register(offset);
+            return unsafe { u64::from_be(dma_address_register.read_volatile()) };
+        }
+        let dma_address_register: *mut u32 = self.register(offset);
+        unsafe {
+            let high = u32::from_be(dma_address_register.read_volatile());
+            let low = u32::from_be(dma_address_register.add(1).read_volatile());
+            (high as u64) << 32 | low as u64
+        }
+    }
+
+    pub(crate) fn start_dma(&self, address: u64, address_reset: bool) {
+        let offset = self.dma_offset();
+        // https://gitlab.com/qemu-project/qemu/-/blob/v7.0.0/docs/specs/fw_cfg.txt#L167
+        // The DMA address register is 64-bit and big-endian.
+        // A single 64-bit write sets it entirely and triggers DMA.
+        #[cfg(target_pointer_width = "64")]
+        if self.register::<u8>(offset) as usize % size_of::<u64>() == 0 {
+            let dma_address_register: *mut u64 = self.register(offset);
+            unsafe { dma_address_register.write_volatile(address.to_be()) };
+            return;
+        }
+        let dma_address_register: *mut u32 = self.register(offset);
+        unsafe {
+            // Otherwise, writing its lower half is what triggers DMA,
+            // so write these half separately to control their order:
+            let register_high = dma_address_register;
+            let register_low = dma_address_register.add(1); // One u32
+            let address_high = (address >> 32) as u32;
+            let address_low = address as u32;
+            if !address_reset || address_high != 0 {
+                register_high.write_volatile(address_high.to_be());
+                compiler_fence(Ordering::AcqRel);
+            }
+            register_low.write_volatile(address_low.to_be());
+        }
+    }
+}
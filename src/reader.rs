@@ -0,0 +1,119 @@
+use crate::{Error, FwCfg, FwCfgFile, FwCfgKey};
+
+impl FwCfg {
+    /// Return a cursor reading `file` from its start.
+    ///
+    /// # Examples
+    /// ```
+    /// use qemu_fw_cfg::FwCfg;
+    ///
+    /// let mut fw_cfg = unsafe { FwCfg::take().unwrap() };
+    /// let file = fw_cfg.find_file("etc/e820").unwrap().unwrap();
+    /// let mut reader = fw_cfg.reader(&file);
+    /// let mut entry = [0u8; 20];
+    /// while reader.read(&mut entry).unwrap() == entry.len() {
+    ///     // ...
+    /// }
+    /// ```
+    pub fn reader(&mut self, file: &FwCfgFile) -> FwCfgReader<'_> {
+        FwCfgReader {
+            fw_cfg: self,
+            key: file.key(),
+            size: file.size(),
+            offset: 0,
+        }
+    }
+}
+
+/// A cursor over the data of a file, returned by [`FwCfg::reader`].
+///
+/// `FwCfg` keeps track of the selected item and the offset in its data, so
+/// consecutive reads continue without selecting the file again, and seeking
+/// forward only skips the bytes in between, using DMA if available.
+///
+/// With the `embedded-io` feature, this implements `embedded_io::Read`
+/// and `embedded_io::Seek` for filesystem crates reading from a disk image.
+#[derive(Debug)]
+pub struct FwCfgReader<'a> {
+    fw_cfg: &'a mut FwCfg,
+    key: FwCfgKey,
+    size: usize,
+    offset: usize,
+}
+
+impl FwCfgReader<'_> {
+    /// Read data at the current offset into `buffer`, and advance past it.
+    ///
+    /// Returns the number of bytes read, which is less than `buffer.len()`
+    /// only at the end of the file.
+    pub fn read(&mut self, buffer: &mut [u8]) -> Result<usize, Error> {
+        let len = buffer.len().min(self.remaining());
+        if len == 0 {
+            return Ok(0);
+        }
+        let data = buffer.as_mut_ptr();
+        unsafe { self.fw_cfg.read_item_at(self.key, self.offset, data, len)? };
+        self.offset += len;
+        Ok(len)
+    }
+
+    /// Move to `offset` in the file.
+    ///
+    /// Nothing is read from the device until the next [`FwCfgReader::read`].
+    /// Seeking backward selects the file again and skips from its start.
+    pub fn seek(&mut self, offset: usize) {
+        self.offset = offset;
+    }
+
+    /// The current offset in the file.
+    pub fn position(&self) -> usize {
+        self.offset
+    }
+
+    /// The size of the file.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// The number of bytes left to read.
+    pub fn remaining(&self) -> usize {
+        self.size.saturating_sub(self.offset)
+    }
+}
+
+#[cfg(feature = "embedded-io")]
+impl embedded_io::ErrorType for FwCfgReader<'_> {
+    type Error = Error;
+}
+
+#[cfg(feature = "embedded-io")]
+impl embedded_io::Read for FwCfgReader<'_> {
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, Error> {
+        FwCfgReader::read(self, buffer)
+    }
+}
+
+#[cfg(feature = "embedded-io")]
+impl embedded_io::Seek for FwCfgReader<'_> {
+    fn seek(&mut self, pos: embedded_io::SeekFrom) -> Result<u64, Error> {
+        use core::convert::TryFrom;
+        use embedded_io::SeekFrom;
+
+        let offset = match pos {
+            SeekFrom::Start(offset) => usize::try_from(offset).ok(),
+            SeekFrom::End(delta) => offset_by(self.size, delta),
+            SeekFrom::Current(delta) => offset_by(self.offset, delta),
+        };
+        let offset = offset.ok_or(Error::OutOfRange)?;
+        FwCfgReader::seek(self, offset);
+        Ok(offset as u64)
+    }
+}
+
+#[cfg(feature = "embedded-io")]
+fn offset_by(base: usize, delta: i64) -> Option<usize> {
+    use core::convert::TryFrom;
+
+    let delta = isize::try_from(delta).ok()?;
+    base.checked_add_signed(delta)
+}
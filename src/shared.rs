@@ -0,0 +1,170 @@
+use crate::FwCfg;
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::hint::spin_loop;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// A [`FwCfg`] shared between threads or processors behind a spin lock.
+///
+/// This can be stored in a `static` so that several drivers can access
+/// fw_cfg. Holding the guard returned by [`SharedFwCfg::lock`] keeps
+/// sequences of operations, such as selecting and reading an item,
+/// from being interleaved with others.
+///
+/// # Examples
+/// ```
+/// use qemu_fw_cfg::{FwCfg, SharedFwCfg};
+///
+/// static FW_CFG: SharedFwCfg = SharedFwCfg::new();
+///
+/// FW_CFG.set(unsafe { FwCfg::take().unwrap() }).unwrap();
+///
+/// // In any driver:
+/// let mut fw_cfg = FW_CFG.lock().unwrap();
+/// let file = fw_cfg.find_file("etc/acpi/tables").unwrap().unwrap();
+/// let data = fw_cfg.read_file(&file).unwrap();
+/// ```
+pub struct SharedFwCfg {
+    locked: AtomicBool,
+    fw_cfg: UnsafeCell<Option<FwCfg>>,
+}
+
+// SAFETY: `fw_cfg` is only accessed while holding the lock.
+unsafe impl Sync for SharedFwCfg {}
+
+impl SharedFwCfg {
+    /// Create an empty `SharedFwCfg`, to be filled with [`SharedFwCfg::set`].
+    pub const fn new() -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            fw_cfg: UnsafeCell::new(None),
+        }
+    }
+
+    /// Store `fw_cfg`, or give it back if another one is already stored.
+    // Giving the value back is the point of the error, so it cannot be boxed.
+    #[allow(clippy::result_large_err)]
+    pub fn set(&self, fw_cfg: FwCfg) -> Result<(), FwCfg> {
+        let mut guard = self.raw_lock();
+        match &*guard {
+            Some(_) => Err(fw_cfg),
+            None => {
+                *guard = Some(fw_cfg);
+                Ok(())
+            }
+        }
+    }
+
+    /// Remove the stored `FwCfg`, waiting until it is unlocked.
+    pub fn take(&self) -> Option<FwCfg> {
+        self.raw_lock().take()
+    }
+
+    /// Lock the stored `FwCfg`, spinning until it is available.
+    ///
+    /// Returns `None` if nothing is stored.
+    pub fn lock(&self) -> Option<SharedFwCfgGuard<'_>> {
+        SharedFwCfgGuard::new(self.raw_lock())
+    }
+
+    /// Lock the stored `FwCfg` if it is not already locked.
+    ///
+    /// Returns `None` if it is locked or nothing is stored.
+    pub fn try_lock(&self) -> Option<SharedFwCfgGuard<'_>> {
+        SharedFwCfgGuard::new(self.raw_try_lock()?)
+    }
+
+    fn raw_lock(&self) -> RawGuard<'_> {
+        loop {
+            if let Some(guard) = self.raw_try_lock() {
+                return guard;
+            }
+            while self.locked.load(Ordering::Relaxed) {
+                spin_loop();
+            }
+        }
+    }
+
+    fn raw_try_lock(&self) -> Option<RawGuard<'_>> {
+        self.locked
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .ok()?;
+        Some(RawGuard { shared: self })
+    }
+}
+
+impl Default for SharedFwCfg {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for SharedFwCfg {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let mut debug = fmt.debug_struct("SharedFwCfg");
+        match self.try_lock() {
+            Some(fw_cfg) => debug.field("fw_cfg", &*fw_cfg),
+            None => debug.field("fw_cfg", &format_args!("<locked or empty>")),
+        };
+        debug.finish()
+    }
+}
+
+struct RawGuard<'a> {
+    shared: &'a SharedFwCfg,
+}
+
+impl Deref for RawGuard<'_> {
+    type Target = Option<FwCfg>;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.shared.fw_cfg.get() }
+    }
+}
+
+impl DerefMut for RawGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.shared.fw_cfg.get() }
+    }
+}
+
+impl Drop for RawGuard<'_> {
+    fn drop(&mut self) {
+        self.shared.locked.store(false, Ordering::Release);
+    }
+}
+
+/// A guard giving exclusive access to the `FwCfg` stored in a [`SharedFwCfg`].
+///
+/// The lock is released when the guard is dropped.
+pub struct SharedFwCfgGuard<'a> {
+    guard: RawGuard<'a>,
+}
+
+impl<'a> SharedFwCfgGuard<'a> {
+    fn new(guard: RawGuard<'a>) -> Option<Self> {
+        guard.as_ref()?;
+        Some(Self { guard })
+    }
+}
+
+impl Deref for SharedFwCfgGuard<'_> {
+    type Target = FwCfg;
+
+    fn deref(&self) -> &FwCfg {
+        self.guard.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for SharedFwCfgGuard<'_> {
+    fn deref_mut(&mut self) -> &mut FwCfg {
+        self.guard.as_mut().unwrap()
+    }
+}
+
+impl fmt::Debug for SharedFwCfgGuard<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, fmt)
+    }
+}
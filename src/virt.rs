@@ -0,0 +1,34 @@
+use crate::{Error, FwCfg};
+
+/// The base address of the memory-mapped fw_cfg device
+/// on the QEMU `virt` machine for this architecture.
+///
+/// This allows early boot code to build `FwCfg` before parsing the device
+/// tree, see [`FwCfg::take_virt`].
+// https://gitlab.com/qemu-project/qemu/-/blob/v8.0.0/hw/riscv/virt.c#L95
+#[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+pub const VIRT_BASE_ADDRESS: usize = 0x1010_0000;
+
+/// The base address of the memory-mapped fw_cfg device
+/// on the QEMU `virt` machine for this architecture.
+///
+/// This allows early boot code to build `FwCfg` before parsing the device
+/// tree, see [`FwCfg::take_virt`].
+// https://gitlab.com/qemu-project/qemu/-/blob/v8.0.0/include/hw/loongarch/virt.h#L29
+#[cfg(target_arch = "loongarch64")]
+pub const VIRT_BASE_ADDRESS: usize = 0x1e02_0000;
+
+impl FwCfg {
+    /// Take the fw_cfg device of the QEMU `virt` machine,
+    /// at [`VIRT_BASE_ADDRESS`].
+    ///
+    /// See [`FwCfg::take_memory_mapped`].
+    ///
+    /// # Safety
+    ///
+    /// This may only be called when running on the QEMU `virt` machine,
+    /// with [`VIRT_BASE_ADDRESS`] mapped to the same physical address.
+    pub unsafe fn take_virt() -> Result<FwCfg, Error> {
+        Self::take_memory_mapped(VIRT_BASE_ADDRESS as *mut ())
+    }
+}
@@ -5,16 +5,24 @@ const IO_PORT_SELECTOR: u16 = 0x510;
 const IO_PORT_DATA: u16 = 0x511;
 const IO_PORT_DMA_ADDRESS: u16 = 0x514;
 
-unsafe fn in_u8(address: u16) -> u8 {
-    let ret: u8;
+unsafe fn in_u32(address: u16) -> u32 {
+    let ret: u32;
     asm!(
-        "in al, dx",
-        out("al") ret,
+        "in eax, dx",
+        out("eax") ret,
         in("dx") address,
     );
     ret
 }
 
+unsafe fn out_u8(address: u16, data: u8) {
+    asm!(
+        "out dx, al",
+        in("dx") address,
+        in("al") data,
+    );
+}
+
 unsafe fn out_u16(address: u16, data: u16) {
     asm!(
         "out dx, ax",
@@ -35,14 +43,32 @@ pub(crate) unsafe fn write_selector(key: u16) {
     out_u16(IO_PORT_SELECTOR, key);
 }
 
-pub(crate) unsafe fn read_data(buffer: &mut [u8]) {
-    for i in buffer {
-        *i = in_u8(IO_PORT_DATA);
+pub(crate) unsafe fn read_data(data: *mut u8, len: usize) {
+    // String I/O lets the hypervisor handle the whole buffer
+    // in one exit instead of exiting once per byte.
+    asm!(
+        "rep insb",
+        in("dx") IO_PORT_DATA,
+        inout("edi") data => _,
+        inout("ecx") len => _,
+        options(nostack, preserves_flags),
+    );
+}
+
+pub(crate) unsafe fn write_data(data: &[u8]) {
+    for &i in data {
+        out_u8(IO_PORT_DATA, i);
     }
 }
 
-pub(crate) unsafe fn start_dma(access: &crate::FwCfgDmaAccess) {
-    let address = access as *const crate::FwCfgDmaAccess as u64;
+pub(crate) unsafe fn read_dma_signature() -> u64 {
+    // https://gitlab.com/qemu-project/qemu/-/blob/v7.0.0/docs/specs/fw_cfg.txt#L170
+    let high = u32::from_be(in_u32(IO_PORT_DMA_ADDRESS));
+    let low = u32::from_be(in_u32(IO_PORT_DMA_ADDRESS + 4));
+    (high as u64) << 32 | low as u64
+}
+
+pub(crate) unsafe fn start_dma(address: u64, address_reset: bool) {
     // https://gitlab.com/qemu-project/qemu/-/blob/v7.0.0/docs/specs/fw_cfg.txt#L167
     // The DMA address register is 64-bit and big-endian,
     // but I/O ports only support 32-bit writes.
@@ -50,7 +76,9 @@ pub(crate) unsafe fn start_dma(access: &crate::FwCfgDmaAccess) {
     let port_low = IO_PORT_DMA_ADDRESS + 4;
     let address_high = (address >> 32) as u32;
     let address_low = address as u32;
-    out_u32(port_high, address_high.to_be());
+    if !address_reset || address_high != 0 {
+        out_u32(port_high, address_high.to_be());
+    }
     // Write the lower bits last as this is what triggers DMA, do it last
     out_u32(port_low, address_low.to_be());
 }
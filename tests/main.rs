@@ -2,25 +2,49 @@
 #![no_main]
 #![cfg_attr(feature = "alloc", feature(default_alloc_error_handler))]
 
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
 use core::fmt::Write;
+use core::mem::MaybeUninit;
+use core::pin::pin;
+use core::ptr::addr_of_mut;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use qemu_fw_cfg::igd::{self, Igd, IgdConfigSpace, OpRegion};
+use qemu_fw_cfg::{
+    DmaBatch, DmaCache, Error, FwCfgBlockDevice, FwCfgDirEntry, FwCfgDirectory, FwCfgFeatures,
+    FwCfgFile, FwCfgKey, FwCfgLimits, FwCfgQuirks, PollBudget, SharedFwCfg,
+};
 
 mod shared;
 
 const DATA_INPUT_TXT: &'static [u8] = include_bytes!("input.txt");
 
-#[cfg_attr(not(target_arch = "riscv32"), no_mangle)]
+// Small enough to split reads into many DMA transfers
+const DMA_BUFFER_SIZE: usize = 64;
+static mut DMA_BUFFER: [u64; DMA_BUFFER_SIZE / 8] = [0; DMA_BUFFER_SIZE / 8];
+
+#[cfg_attr(not(any(target_arch = "riscv32", target_arch = "riscv64")), no_mangle)]
 fn main() {
     let mut fw_cfg = unsafe { shared::fw_cfg() };
 
     // File exist
-    let file_input_txt = fw_cfg.find_file("opt/input.txt").unwrap();
+    let file_input_txt = fw_cfg.find_file("opt/input.txt").unwrap().unwrap();
+
+    // File name
+    assert_eq!(file_input_txt.name(), "opt/input.txt");
+    assert_eq!(file_input_txt.name_bytes(), b"opt/input.txt");
+    assert_eq!(file_input_txt.try_name(), Ok("opt/input.txt"));
 
     // File not exist
-    assert!(fw_cfg.find_file("opt/not_found.txt").is_none());
+    assert!(fw_cfg.find_file("opt/not_found.txt").unwrap().is_none());
 
     // Long file name
     fw_cfg
         .find_file("opt/567890123456789012345678901234567890123456789012345")
+        .unwrap()
         .unwrap();
 
     // Multiple files
@@ -30,7 +54,7 @@ fn main() {
         ("opt/input.txt", None),
         ("opt/not_found.txt", Some(file_input_txt.clone())),
     ];
-    fw_cfg.find_files(&mut files);
+    fw_cfg.find_files(&mut files).unwrap();
     assert_eq!(
         files.map(|i| i.1),
         [
@@ -41,23 +65,580 @@ fn main() {
         ]
     );
 
+    // Directory index
+    let directory = FwCfgDirectory::<[FwCfgFile; 64]>::read_array(&mut fw_cfg).unwrap();
+    assert!(directory.is_sorted());
+    assert_eq!(directory.find_file("opt/input.txt"), Some(&file_input_txt));
+    assert_eq!(directory.find_file("opt/not_found.txt"), None);
+    assert_eq!(directory.iter_files_with_prefix("opt/").count(), 2);
+    assert_eq!(directory.iter_files_matching("opt/*.txt").count(), 1);
+    assert_eq!(directory.iter_files_matching("*").count(), 0);
+    assert!(directory
+        .read_dir("")
+        .any(|entry| entry == FwCfgDirEntry::Dir("opt")));
+    assert_eq!(directory.read_dir("opt").count(), 2);
+    assert!(directory
+        .read_dir("opt/")
+        .any(|entry| entry == FwCfgDirEntry::File(&file_input_txt)));
+
+    // Prefix and glob enumeration
+    assert_eq!(fw_cfg.iter_files_with_prefix("opt/").count(), 2);
+    let mut matching = fw_cfg.iter_files_matching("opt/*.txt");
+    assert_eq!(matching.next(), Some(Ok(file_input_txt.clone())));
+    assert_eq!(matching.next(), None);
+    drop(matching);
+    assert_eq!(
+        FwCfgDirectory::<[FwCfgFile; 1]>::read_array(&mut fw_cfg).unwrap_err(),
+        Error::TooManyEntries
+    );
+    #[cfg(feature = "alloc")]
+    {
+        let directory = FwCfgDirectory::<Vec<FwCfgFile>>::read_vec(&mut fw_cfg).unwrap();
+        assert_eq!(directory.find_file("opt/input.txt"), Some(&file_input_txt));
+    }
+
     // Read file
     #[cfg(feature = "alloc")]
-    assert_eq!(DATA_INPUT_TXT, fw_cfg.read_file(&file_input_txt));
+    assert_eq!(DATA_INPUT_TXT, fw_cfg.read_file(&file_input_txt).unwrap());
 
     // Read file with buffer
     let mut buffer = [0u8; DATA_INPUT_TXT.len()];
-    fw_cfg.read_file_to_buffer(&file_input_txt, &mut buffer);
+    let read = fw_cfg.read_file_to_buffer(&file_input_txt, &mut buffer);
+    assert_eq!(read, Ok(DATA_INPUT_TXT.len()));
     assert_eq!(DATA_INPUT_TXT, buffer);
 
-    // Small buffer
+    // Read file into uninitialized buffer
+    let mut buffer = [MaybeUninit::uninit(); DATA_INPUT_TXT.len() + 1];
+    let data = fw_cfg
+        .read_file_to_uninit(&file_input_txt, &mut buffer)
+        .unwrap();
+    assert_eq!(DATA_INPUT_TXT, data);
+
+    // Read file with fallible allocation
+    #[cfg(feature = "alloc")]
+    assert_eq!(
+        DATA_INPUT_TXT,
+        fw_cfg.try_read_file(&file_input_txt).unwrap()
+    );
+
+    // Cursor
+    let mut reader = fw_cfg.reader(&file_input_txt);
+    assert_eq!(reader.size(), DATA_INPUT_TXT.len());
+    let mut chunk = [0u8; 100];
+    assert_eq!(reader.read(&mut chunk), Ok(chunk.len()));
+    assert_eq!(DATA_INPUT_TXT[..100], chunk);
+    assert_eq!(reader.read(&mut chunk), Ok(chunk.len()));
+    assert_eq!(DATA_INPUT_TXT[100..200], chunk);
+    reader.seek(1000);
+    assert_eq!(reader.read(&mut chunk), Ok(chunk.len()));
+    assert_eq!(DATA_INPUT_TXT[1000..1100], chunk);
+    reader.seek(50);
+    assert_eq!(reader.read(&mut chunk), Ok(chunk.len()));
+    assert_eq!(DATA_INPUT_TXT[50..150], chunk);
+    reader.seek(DATA_INPUT_TXT.len() - 10);
+    assert_eq!(reader.read(&mut chunk), Ok(10));
+    assert_eq!(DATA_INPUT_TXT[DATA_INPUT_TXT.len() - 10..], chunk[..10]);
+    assert_eq!(reader.read(&mut chunk), Ok(0));
+    assert_eq!(reader.position(), DATA_INPUT_TXT.len());
+
+    // Block device
+    let mut device = FwCfgBlockDevice::<512>::new(&mut fw_cfg, &file_input_txt);
+    let last = DATA_INPUT_TXT.len() / 512;
+    assert_eq!(device.num_blocks(), last as u64 + 1);
+    assert_eq!(
+        device.read_block(2).unwrap()[..],
+        DATA_INPUT_TXT[1024..1536]
+    );
+    assert_eq!(device.read_block(0).unwrap()[..], DATA_INPUT_TXT[..512]);
+    let mut blocks = [0u8; 1024];
+    device.read_blocks(last as u64 - 1, &mut blocks).unwrap();
+    assert_eq!(blocks[..512], DATA_INPUT_TXT[(last - 1) * 512..last * 512]);
+    let tail = DATA_INPUT_TXT.len() - last * 512;
+    assert_eq!(blocks[512..512 + tail], DATA_INPUT_TXT[last * 512..]);
+    assert!(blocks[512 + tail..].iter().all(|&byte| byte == 0));
+    assert_eq!(
+        device.read_blocks(last as u64, &mut blocks),
+        Err(Error::OutOfRange)
+    );
+    assert_eq!(device.read_block(0).unwrap()[..], DATA_INPUT_TXT[..512]);
+
+    #[cfg(feature = "embedded-sdmmc")]
+    {
+        use embedded_sdmmc::{Block, BlockCount, BlockDevice, BlockIdx};
+
+        let device = FwCfgBlockDevice::<512>::new(&mut fw_cfg, &file_input_txt);
+        assert_eq!(
+            BlockDevice::num_blocks(&device),
+            Ok(BlockCount(last as u32 + 1))
+        );
+        let mut blocks = [Block::new(), Block::new()];
+        device.read(&mut blocks, BlockIdx(1), "test").unwrap();
+        assert_eq!(blocks[0].contents[..], DATA_INPUT_TXT[512..1024]);
+        assert_eq!(blocks[1].contents[..], DATA_INPUT_TXT[1024..1536]);
+        assert_eq!(
+            device.read(&mut blocks, BlockIdx(last as u32), "test"),
+            Err(Error::OutOfRange)
+        );
+        assert_eq!(device.write(&blocks, BlockIdx(0)), Err(Error::ReadOnly));
+    }
+
+    #[cfg(feature = "embedded-io")]
+    {
+        use embedded_io::{Read, Seek, SeekFrom};
+
+        let mut reader = fw_cfg.reader(&file_input_txt);
+        let mut chunk = [0u8; 100];
+        let end = DATA_INPUT_TXT.len() as u64;
+        assert_eq!(Seek::seek(&mut reader, SeekFrom::End(-100)), Ok(end - 100));
+        reader.read_exact(&mut chunk).unwrap();
+        assert_eq!(DATA_INPUT_TXT[DATA_INPUT_TXT.len() - 100..], chunk);
+        assert_eq!(
+            Seek::seek(&mut reader, SeekFrom::Current(-200)),
+            Ok(end - 200)
+        );
+        assert_eq!(Read::read(&mut reader, &mut chunk), Ok(chunk.len()));
+        assert_eq!(
+            Seek::seek(&mut reader, SeekFrom::Current(-(end as i64) - 1)),
+            Err(Error::OutOfRange)
+        );
+    }
+
+    // Truncated buffer
     let mut buffer = [0u8; DATA_INPUT_TXT.len() / 2];
-    fw_cfg.read_file_to_buffer(&file_input_txt, &mut buffer);
+    let read = fw_cfg.read_file_to_buffer(&file_input_txt, &mut buffer);
+    assert_eq!(read, Ok(buffer.len()));
     assert_eq!(DATA_INPUT_TXT[..buffer.len()], buffer);
+    let mut buffer = [MaybeUninit::uninit(); DATA_INPUT_TXT.len() - 1];
+    let data = fw_cfg
+        .read_file_to_uninit(&file_input_txt, &mut buffer)
+        .unwrap();
+    assert_eq!(DATA_INPUT_TXT[..DATA_INPUT_TXT.len() - 1], *data);
+    #[cfg(feature = "alloc")]
+    assert_eq!(
+        alloc::format!("{}", Error::TruncatedBuffer { copied: 1, size: 2 }),
+        "buffer truncated, copied 1 of 2 bytes"
+    );
+
+    // Raw selector keys
+    assert!(file_input_txt.key().is_file());
+    let mut signature = [0u8; 4];
+    fw_cfg
+        .read_key(FwCfgKey::SIGNATURE, &mut signature)
+        .unwrap();
+    assert_eq!(&signature, b"QEMU");
+    let mut nb_cpus = [0u8; 2];
+    fw_cfg.read_key(FwCfgKey::NB_CPUS, &mut nb_cpus).unwrap();
+    assert_eq!(u16::from_le_bytes(nb_cpus), 1);
+
+    // Features
+    let features = fw_cfg.features();
+    assert!(features.contains(FwCfgFeatures::TRADITIONAL_INTERFACE | FwCfgFeatures::DMA));
+
+    // IGD passthrough, without an assigned IGD
+    let igd = Igd::find(&mut fw_cfg).unwrap();
+    assert_eq!(igd.opregion_size(), None);
+    assert_eq!(igd.bdsm_size(), None);
+    let mut opregion = [0u8; 32];
+    assert_eq!(
+        igd.read_opregion(&mut fw_cfg, &mut opregion),
+        Err(Error::InvalidOpRegion)
+    );
+    assert_eq!(OpRegion::parse(&opregion), Err(Error::InvalidOpRegion));
+    opregion[..16].copy_from_slice(igd::OPREGION_SIGNATURE);
+    assert_eq!(OpRegion::parse(&opregion).unwrap().size(), opregion.len());
+    let mut config_space = RecordingConfigSpace::default();
+    igd::set_opregion_address(&mut config_space, 0x7fff_e000);
+    igd::set_bdsm_address(&mut config_space, 0x7f00_0000);
+    assert_eq!(
+        config_space.writes,
+        [(igd::ASLS, 0x7fff_e000), (igd::BDSM, 0x7f00_0000)]
+    );
+
+    // Hardened limits
+    fw_cfg.set_limits(FwCfgLimits::HARDENED);
+    assert_eq!(
+        fw_cfg.find_file("opt/input.txt").unwrap(),
+        Some(file_input_txt.clone())
+    );
+    fw_cfg.set_limits(FwCfgLimits {
+        max_entries: 1,
+        ..FwCfgLimits::HARDENED
+    });
+    assert_eq!(
+        fw_cfg.find_file("opt/input.txt"),
+        Err(Error::TooManyEntries)
+    );
+    #[cfg(feature = "alloc")]
+    {
+        fw_cfg.set_limits(FwCfgLimits {
+            max_item_size: DATA_INPUT_TXT.len() - 1,
+            ..FwCfgLimits::HARDENED
+        });
+        assert_eq!(fw_cfg.read_file(&file_input_txt), Err(Error::ItemTooLarge));
+    }
+    fw_cfg.set_limits(FwCfgLimits::default());
 
     // This file is not writeable
     let result = fw_cfg.write_to_file(&file_input_txt, b" ");
-    assert_eq!(result, Err(qemu_fw_cfg::FwCfgWriteError::DmaFailed));
+    assert_eq!(result, Err(Error::DmaFailed));
+
+    // Legacy writes are not used when DMA is available
+    fw_cfg.set_legacy_writes(true);
+    let result = fw_cfg.write_to_file(&file_input_txt, b" ");
+    assert_eq!(result, Err(Error::DmaFailed));
+    fw_cfg.set_legacy_writes(false);
+
+    // Direct DMA disallowed without a DMA buffer
+    fw_cfg.set_direct_dma(false);
+    let result = fw_cfg.write_to_file(&file_input_txt, b" ");
+    assert_eq!(result, Err(Error::DmaNotAvailable));
+
+    // Read file through a small DMA buffer
+    unsafe { fw_cfg.set_dma_buffer(addr_of_mut!(DMA_BUFFER).cast(), DMA_BUFFER_SIZE) }.unwrap();
+    let mut buffer = [0u8; DATA_INPUT_TXT.len()];
+    let read = fw_cfg.read_file_to_buffer(&file_input_txt, &mut buffer);
+    assert_eq!(read, Ok(DATA_INPUT_TXT.len()));
+    assert_eq!(DATA_INPUT_TXT, buffer);
+    #[cfg(feature = "alloc")]
+    assert_eq!(DATA_INPUT_TXT, fw_cfg.read_file(&file_input_txt).unwrap());
+    let mut reader = fw_cfg.reader(&file_input_txt);
+    let mut chunk = [0u8; 100];
+    reader.seek(1000);
+    assert_eq!(reader.read(&mut chunk), Ok(chunk.len()));
+    assert_eq!(DATA_INPUT_TXT[1000..1100], chunk);
+    let result = fw_cfg.write_to_file(&file_input_txt, b" ");
+    assert_eq!(result, Err(Error::DmaFailed));
+    fw_cfg.remove_dma_buffer().unwrap();
+    fw_cfg.set_direct_dma(true);
+
+    // Identity-mapped address translation
+    fw_cfg.set_virt_to_phys(&|virt: usize| Some(virt as u64));
+    let result = fw_cfg.write_to_file(&file_input_txt, b" ");
+    assert_eq!(result, Err(Error::DmaFailed));
+
+    // Unmapped address translation
+    fw_cfg.set_virt_to_phys(&|_| None);
+    let result = fw_cfg.write_to_file(&file_input_txt, b" ");
+    assert_eq!(result, Err(Error::AddressNotMapped));
+
+    // Non-contiguous address translation
+    fw_cfg.set_virt_to_phys(&|virt: usize| Some(virt as u64 * 2));
+    let result = fw_cfg.write_to_file(&file_input_txt, &DATA_INPUT_TXT[..4097]);
+    assert_eq!(result, Err(Error::NonContiguousBuffer));
+    fw_cfg.remove_virt_to_phys();
+
+    // Read file to physical memory, identity-mapped here
+    let mut buffer = [0u8; DATA_INPUT_TXT.len()];
+    let phys_addr = buffer.as_mut_ptr() as u64;
+    let result = unsafe { fw_cfg.read_file_to_phys(&file_input_txt, phys_addr, buffer.len()) };
+    assert_eq!(result, Ok(()));
+    assert_eq!(DATA_INPUT_TXT, buffer);
+    let result = unsafe { fw_cfg.read_file_to_phys(&file_input_txt, phys_addr, 1) };
+    assert_eq!(result, Err(Error::SizeMismatch));
+    let mut buffer = [0u8; DATA_INPUT_TXT.len()];
+    let phys_addr = buffer.as_mut_ptr() as u64;
+    fw_cfg.set_direct_dma(false);
+    let result = unsafe { fw_cfg.read_file_to_phys(&file_input_txt, phys_addr, buffer.len()) };
+    assert_eq!(result, Ok(()));
+    assert_eq!(DATA_INPUT_TXT, buffer);
+    fw_cfg.set_virt_to_phys(&|virt: usize| Some(virt as u64));
+    let result = unsafe { fw_cfg.read_file_to_phys(&file_input_txt, phys_addr, buffer.len()) };
+    assert_eq!(result, Err(Error::AddressNotMapped));
+    fw_cfg.remove_virt_to_phys();
+    fw_cfg.set_direct_dma(true);
+
+    // Read and write file with async DMA
+    let mut buffer = [0u8; DATA_INPUT_TXT.len()];
+    shared::block_on(unsafe { fw_cfg.read_dma(&file_input_txt, &mut buffer) }).unwrap();
+    assert_eq!(DATA_INPUT_TXT, buffer);
+    let result = shared::block_on(unsafe { fw_cfg.write_dma(&file_input_txt, b" ") });
+    assert_eq!(result, Err(Error::DmaFailed));
+
+    // Data register throughput
+    #[cfg(target_arch = "x86")]
+    {
+        const ROUNDS: u64 = 16;
+        let bytes = ROUNDS * DATA_INPUT_TXT.len() as u64;
+        let mut buffer = [0u8; DATA_INPUT_TXT.len()];
+
+        let start = shared::rdtsc();
+        for _ in 0..ROUNDS {
+            unsafe { shared::read_bytewise(file_input_txt.key().0, &mut buffer) };
+        }
+        let bytewise = (shared::rdtsc() - start) / bytes;
+        assert_eq!(DATA_INPUT_TXT, buffer);
+        // `fw_cfg` does not know what `read_bytewise` selected,
+        // so select another item through it before reading the file again.
+        let mut signature = [0u8; 4];
+        fw_cfg
+            .read_key(FwCfgKey::SIGNATURE, &mut signature)
+            .unwrap();
+        assert_eq!(&signature, b"QEMU");
+
+        let start = shared::rdtsc();
+        for _ in 0..ROUNDS {
+            fw_cfg
+                .read_file_to_buffer(&file_input_txt, &mut buffer)
+                .unwrap();
+        }
+        let string_io = (shared::rdtsc() - start) / bytes;
+        assert_eq!(DATA_INPUT_TXT, buffer);
+
+        let start = shared::rdtsc();
+        for _ in 0..ROUNDS {
+            shared::block_on(unsafe { fw_cfg.read_dma(&file_input_txt, &mut buffer) }).unwrap();
+        }
+        let dma = (shared::rdtsc() - start) / bytes;
+        assert_eq!(DATA_INPUT_TXT, buffer);
+
+        writeln!(
+            shared::Writer,
+            "Cycles per byte: in loop {}, rep insb {}, DMA {}",
+            bytewise,
+            string_io,
+            dma
+        )
+        .unwrap();
+    }
+
+    // DMA batch
+    let half = DATA_INPUT_TXT.len() / 2;
+    let mut first = [0u8; 16];
+    let mut second = [0u8; 16];
+    let mut third = [0u8; 16];
+    let mut third_again = [0u8; 16];
+    let mut batch = DmaBatch::<6>::new();
+    batch
+        .read_file(&file_input_txt, &mut first)
+        .skip(half - 16)
+        .read(&mut second)
+        .write_file(&file_input_txt, b" ")
+        .read(&mut third)
+        .read_file(&file_input_txt, &mut third_again);
+    assert_eq!(
+        fw_cfg.run_dma_batch(batch),
+        [
+            Some(Ok(())),
+            Some(Ok(())),
+            Some(Ok(())),
+            Some(Err(Error::DmaFailed)),
+            None,
+            Some(Ok(())),
+        ]
+    );
+    assert_eq!(DATA_INPUT_TXT[..16], first);
+    assert_eq!(DATA_INPUT_TXT[half..half + 16], second);
+    assert_eq!([0u8; 16], third);
+    assert_eq!(DATA_INPUT_TXT[..16], third_again);
+
+    // DMA polling policy
+    fw_cfg.set_dma_poll(&PollBudget(1_000_000));
+    let result = fw_cfg.write_to_file(&file_input_txt, b" ");
+    assert_eq!(result, Err(Error::DmaFailed));
+    fw_cfg.remove_dma_poll();
+
+    // DMA timeout, simulating a device that does not complete the operation
+    static STALLING: StallingCache = StallingCache {
+        stalling: AtomicBool::new(true),
+        descriptor: AtomicUsize::new(0),
+    };
+    unsafe { fw_cfg.set_dma_buffer(addr_of_mut!(DMA_BUFFER).cast(), DMA_BUFFER_SIZE) }.unwrap();
+    fw_cfg.set_dma_poll(&PollBudget(10));
+    fw_cfg.set_dma_cache(&STALLING);
+    let mut buffer = [0u8; DATA_INPUT_TXT.len()];
+    assert_eq!(
+        fw_cfg.read_file_to_buffer(&file_input_txt, &mut buffer),
+        Err(Error::Timeout)
+    );
+    assert_eq!(
+        fw_cfg.read_file_to_buffer(&file_input_txt, &mut buffer),
+        Err(Error::DmaPending)
+    );
+    let result = fw_cfg.write_to_file(&file_input_txt, b" ");
+    assert_eq!(result, Err(Error::DmaPending));
+    let result = fw_cfg.find_file("opt/input.txt");
+    assert_eq!(result, Err(Error::DmaPending));
+    assert_eq!(fw_cfg.remove_dma_buffer(), Err(Error::DmaPending));
+    STALLING.complete();
+    let read = fw_cfg.read_file_to_buffer(&file_input_txt, &mut buffer);
+    assert_eq!(read, Ok(DATA_INPUT_TXT.len()));
+    assert_eq!(DATA_INPUT_TXT, buffer);
+    fw_cfg.remove_dma_buffer().unwrap();
+
+    // DMA timeout without a DMA buffer, staged through memory owned by the crate
+    STALLING.stalling.store(true, Ordering::Relaxed);
+    let result = fw_cfg.write_to_file(&file_input_txt, b" ");
+    assert_eq!(result, Err(Error::Timeout));
+    let result = fw_cfg.write_to_file(&file_input_txt, b" ");
+    assert_eq!(result, Err(Error::DmaPending));
+    STALLING.complete();
+    let result = fw_cfg.write_to_file(&file_input_txt, b" ");
+    assert_eq!(result, Err(Error::DmaFailed));
+
+    // DMA future timeout, leaving the staged transfer pending
+    STALLING.stalling.store(true, Ordering::Relaxed);
+    {
+        let mut future = pin!(unsafe { fw_cfg.read_dma(&file_input_txt, &mut buffer) });
+        assert_eq!(shared::block_on(future.as_mut()), Err(Error::Timeout));
+    }
+    let result = shared::block_on(unsafe { fw_cfg.read_dma(&file_input_txt, &mut buffer) });
+    assert_eq!(result, Err(Error::DmaPending));
+    STALLING.complete();
+    let mut buffer = [0u8; DATA_INPUT_TXT.len()];
+    shared::block_on(unsafe { fw_cfg.read_dma(&file_input_txt, &mut buffer) }).unwrap();
+    assert_eq!(DATA_INPUT_TXT, buffer);
+    fw_cfg.remove_dma_cache();
+    fw_cfg.remove_dma_poll();
+
+    // Cache maintenance
+    static CACHE: CountingCache = CountingCache {
+        cleaned: AtomicUsize::new(0),
+        invalidated: AtomicUsize::new(0),
+    };
+    fw_cfg.set_dma_cache(&CACHE);
+    let mut buffer = [0u8; DATA_INPUT_TXT.len()];
+    shared::block_on(unsafe { fw_cfg.read_dma(&file_input_txt, &mut buffer) }).unwrap();
+    assert_eq!(DATA_INPUT_TXT, buffer);
+    // The descriptor and the payload
+    assert_eq!(CACHE.cleaned.load(Ordering::Relaxed), 2);
+    assert!(CACHE.invalidated.load(Ordering::Relaxed) >= 2);
+    fw_cfg.remove_dma_cache();
+
+    // Shared handle
+    static SHARED: SharedFwCfg = SharedFwCfg::new();
+    assert!(SHARED.lock().is_none());
+    SHARED.set(fw_cfg).unwrap();
+    let file = SHARED.lock().unwrap().find_file("opt/input.txt");
+    assert_eq!(file, Ok(Some(file_input_txt.clone())));
+    let guard = SHARED.lock().unwrap();
+    assert!(SHARED.try_lock().is_none());
+    drop(guard);
+    assert!(SHARED.take().is_some());
+
+    // Singleton ownership
+    let taken = shared::take_fw_cfg().unwrap();
+    assert_eq!(shared::take_fw_cfg().unwrap_err(), Error::AlreadyTaken);
+    drop(taken);
+    shared::take_fw_cfg().unwrap();
+
+    // Quirks
+    let mut fw_cfg = unsafe { shared::fw_cfg_with_quirks(FwCfgQuirks::GENERIC).unwrap() };
+    assert_eq!(fw_cfg.quirks(), FwCfgQuirks::GENERIC);
+    let directory = FwCfgDirectory::<[FwCfgFile; 64]>::read_array(&mut fw_cfg).unwrap();
+    assert!(directory.is_sorted());
+    assert_eq!(directory.find_file("opt/input.txt"), Some(&file_input_txt));
+    drop(fw_cfg);
+    let quirks = FwCfgQuirks {
+        signature: Some(b"XEMU"),
+        ..FwCfgQuirks::QEMU
+    };
+    let result = unsafe { shared::fw_cfg_with_quirks(quirks) };
+    assert_eq!(result.unwrap_err(), Error::InvalidSignature);
+    let quirks = FwCfgQuirks {
+        dma: false,
+        ..FwCfgQuirks::QEMU
+    };
+    let mut fw_cfg = unsafe { shared::fw_cfg_with_quirks(quirks).unwrap() };
+    let result = fw_cfg.write_to_file(&file_input_txt, b" ");
+    assert_eq!(result, Err(Error::DmaNotAvailable));
+    drop(fw_cfg);
+
+    // MMIO layouts
+    #[cfg(any(
+        target_arch = "riscv32",
+        target_arch = "riscv64",
+        target_arch = "loongarch64"
+    ))]
+    {
+        use qemu_fw_cfg::MmioLayout;
+
+        let layout = MmioLayout {
+            data_width: 1,
+            dma_offset: None,
+            ..MmioLayout::VIRT
+        };
+        let mut fw_cfg = unsafe { shared::fw_cfg_with_layout(layout).unwrap() };
+        let mut buffer = [0u8; DATA_INPUT_TXT.len()];
+        fw_cfg
+            .read_file_to_buffer(&file_input_txt, &mut buffer)
+            .unwrap();
+        assert_eq!(DATA_INPUT_TXT, buffer);
+        let result = fw_cfg.write_to_file(&file_input_txt, b" ");
+        assert_eq!(result, Err(Error::DmaNotAvailable));
+        drop(fw_cfg);
+
+        // Unsupported layouts
+        for data_width in [0, 3, 16] {
+            let layout = MmioLayout {
+                data_width,
+                ..MmioLayout::VIRT
+            };
+            let result = unsafe { shared::fw_cfg_with_layout(layout) };
+            assert_eq!(result.unwrap_err(), Error::InvalidLayout);
+        }
+        let layout = MmioLayout {
+            data_offset: 1,
+            ..MmioLayout::VIRT
+        };
+        let result = unsafe { shared::fw_cfg_with_layout(layout) };
+        assert_eq!(result.unwrap_err(), Error::InvalidLayout);
+    }
 
     writeln!(shared::Writer, "✅ Test sucessful").unwrap();
 }
+
+#[derive(Default)]
+struct RecordingConfigSpace {
+    writes: [(u8, u32); 2],
+    len: usize,
+}
+
+impl IgdConfigSpace for RecordingConfigSpace {
+    fn write_u32(&mut self, offset: u8, value: u32) {
+        self.writes[self.len] = (offset, value);
+        self.len += 1;
+    }
+}
+
+/// Simulates a device that does not complete DMA operations while stalling,
+/// by marking the descriptor as still in progress when invalidated.
+struct StallingCache {
+    stalling: AtomicBool,
+    descriptor: AtomicUsize,
+}
+
+impl StallingCache {
+    /// Stop stalling and complete the stalled operation.
+    fn complete(&self) {
+        self.stalling.store(false, Ordering::Relaxed);
+        let descriptor = self.descriptor.load(Ordering::Relaxed) as *mut u32;
+        unsafe { descriptor.write_volatile(0) };
+    }
+}
+
+impl DmaCache for StallingCache {
+    fn clean(&self, _ptr: *const u8, _len: usize) {}
+
+    fn invalidate(&self, ptr: *const u8, len: usize) {
+        // The descriptor is 16 bytes, unlike the payloads in this test.
+        if self.stalling.load(Ordering::Relaxed) && len == 16 {
+            self.descriptor.store(ptr as usize, Ordering::Relaxed);
+            unsafe { (ptr as *mut u32).write_volatile(2u32.to_be()) };
+        }
+    }
+}
+
+struct CountingCache {
+    cleaned: AtomicUsize,
+    invalidated: AtomicUsize,
+}
+
+impl DmaCache for CountingCache {
+    fn clean(&self, _ptr: *const u8, _len: usize) {
+        self.cleaned.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn invalidate(&self, _ptr: *const u8, _len: usize) {
+        self.invalidated.fetch_add(1, Ordering::Relaxed);
+    }
+}
@@ -22,6 +22,23 @@ elif [[ "$ARCH" == riscv32* ]]; then
     else
         riscv32-elf-gdb -ex 'target remote :1234' "$@"
     fi
+elif [[ "$ARCH" == riscv64* ]]; then
+    if [[ -z "$GDB" ]]; then
+        qemu-system-riscv64 $QEMU_OPTS \
+            -machine virt \
+            -bios none \
+            -kernel "$@"
+    else
+        riscv64-elf-gdb -ex 'target remote :1234' "$@"
+    fi
+elif [[ "$ARCH" == loongarch64 ]]; then
+    # The machine has no device for exiting with a status,
+    # so the harness prints it before powering off.
+    output=$(qemu-system-loongarch64 $QEMU_OPTS \
+        -machine virt \
+        -kernel "$@" | tee /dev/stderr)
+    status=$(echo "$output" | sed -n 's/^exit status \([0-9]*\)\r\?$/\1/p')
+    exit ${status:-1}
 else
     echo Unsupported TARGET=$TARGET
 fi
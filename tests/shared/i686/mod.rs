@@ -1,5 +1,5 @@
 use core::arch::{asm, global_asm};
-use qemu_fw_cfg::FwCfg;
+use qemu_fw_cfg::{Error, FwCfg, FwCfgBuilder, FwCfgQuirks};
 
 global_asm!(include_str!("boot.asm"));
 
@@ -11,6 +11,24 @@ unsafe fn outb(port: u16, byte: u8) {
     );
 }
 
+unsafe fn inb(port: u16) -> u8 {
+    let byte: u8;
+    asm!(
+        "in al, dx",
+        out("al") byte,
+        in("dx") port,
+    );
+    byte
+}
+
+unsafe fn outw(port: u16, word: u16) {
+    asm!(
+        "out dx, ax",
+        in("dx") port,
+        in("ax") word,
+    );
+}
+
 #[no_mangle]
 pub extern "C" fn exit(status: u8) -> ! {
     unsafe {
@@ -34,5 +52,25 @@ impl core::fmt::Write for Writer {
 }
 
 pub unsafe fn fw_cfg() -> FwCfg {
-    FwCfg::new_for_x86().unwrap()
+    FwCfg::take().unwrap()
+}
+
+pub fn take_fw_cfg() -> Result<FwCfg, Error> {
+    unsafe { FwCfg::take() }
+}
+
+pub unsafe fn fw_cfg_with_quirks(quirks: FwCfgQuirks) -> Result<FwCfg, Error> {
+    FwCfgBuilder::new().quirks(quirks).take_x86()
+}
+
+pub fn rdtsc() -> u64 {
+    unsafe { core::arch::x86::_rdtsc() }
+}
+
+/// Select `key` and read its data one `in` instruction per byte.
+pub unsafe fn read_bytewise(key: u16, buffer: &mut [u8]) {
+    outw(0x510, key);
+    for byte in buffer {
+        *byte = inb(0x511);
+    }
 }
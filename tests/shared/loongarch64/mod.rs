@@ -0,0 +1,59 @@
+use core::arch::global_asm;
+use core::fmt::Write;
+use qemu_fw_cfg::{Error, FwCfg, FwCfgBuilder, FwCfgQuirks, MmioLayout, VIRT_BASE_ADDRESS};
+
+global_asm!(include_str!("boot.S"));
+
+// https://gitlab.com/qemu-project/qemu/-/blob/v8.0.0/include/hw/loongarch/virt.h
+const UART: *mut u8 = 0x1fe0_01e0 as _;
+const GED_SLEEP_CONTROL: *mut u8 = 0x100e_001c as _;
+
+// https://gitlab.com/qemu-project/qemu/-/blob/v8.0.0/include/hw/acpi/generic_event_device.h#L82-86
+const SLEEP_ENABLE: u8 = 0x20;
+const SLEEP_TYPE_S5: u8 = 0x05 << 2;
+
+/// Power off the machine. It has no device for exiting with a status,
+/// so the status is printed for the runner instead.
+#[no_mangle]
+pub extern "C" fn exit(status: u8) -> ! {
+    writeln!(Writer, "exit status {}", status).unwrap();
+    unsafe { GED_SLEEP_CONTROL.write_volatile(SLEEP_ENABLE | SLEEP_TYPE_S5) };
+    loop {}
+}
+
+pub struct Writer;
+
+impl core::fmt::Write for Writer {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        for b in s.bytes() {
+            unsafe {
+                UART.write_volatile(b);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn base_ptr() -> *mut () {
+    VIRT_BASE_ADDRESS as _
+}
+
+pub unsafe fn fw_cfg() -> FwCfg {
+    FwCfg::take_memory_mapped(base_ptr()).unwrap()
+}
+
+pub fn take_fw_cfg() -> Result<FwCfg, Error> {
+    unsafe { FwCfg::take_virt() }
+}
+
+pub unsafe fn fw_cfg_with_quirks(quirks: FwCfgQuirks) -> Result<FwCfg, Error> {
+    FwCfgBuilder::new()
+        .quirks(quirks)
+        .take_memory_mapped(base_ptr())
+}
+
+pub unsafe fn fw_cfg_with_layout(layout: MmioLayout) -> Result<FwCfg, Error> {
+    FwCfgBuilder::new()
+        .layout(layout)
+        .take_memory_mapped(base_ptr())
+}
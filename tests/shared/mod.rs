@@ -2,8 +2,12 @@
 #[path = "i686/mod.rs"]
 mod arch;
 
-#[cfg(target_arch = "riscv32")]
-#[path = "riscv32/mod.rs"]
+#[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+#[path = "riscv/mod.rs"]
+mod arch;
+
+#[cfg(target_arch = "loongarch64")]
+#[path = "loongarch64/mod.rs"]
 mod arch;
 
 pub use arch::*;
@@ -11,9 +15,12 @@ pub use arch::*;
 use core::{
     alloc::{GlobalAlloc, Layout},
     cell::UnsafeCell,
+    future::Future,
     panic::PanicInfo,
+    pin::Pin,
     ptr::null_mut,
     sync::atomic::{AtomicUsize, Ordering},
+    task::{Context, Poll, RawWaker, RawWakerVTable, Waker},
 };
 
 #[macro_export]
@@ -38,6 +45,24 @@ fn panic(info: &PanicInfo) -> ! {
     exit(1)
 }
 
+/// Poll `future` in a busy loop until it completes.
+pub fn block_on<F: Future>(mut future: F) -> F::Output {
+    const VTABLE: RawWakerVTable = RawWakerVTable::new(
+        |_| RawWaker::new(null_mut(), &VTABLE),
+        |_| {},
+        |_| {},
+        |_| {},
+    );
+    let waker = unsafe { Waker::from_raw(RawWaker::new(null_mut(), &VTABLE)) };
+    let mut context = Context::from_waker(&waker);
+    let mut future = unsafe { Pin::new_unchecked(&mut future) };
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+            return output;
+        }
+    }
+}
+
 const HEAP_SIZE: usize = 1 * 1024 * 1024;
 
 #[global_allocator]
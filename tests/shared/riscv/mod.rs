@@ -1,6 +1,6 @@
 use core::ptr::null_mut;
 use core::sync::atomic::{AtomicPtr, Ordering};
-use qemu_fw_cfg::FwCfg;
+use qemu_fw_cfg::{Error, FwCfg, FwCfgBuilder, FwCfgQuirks, MmioLayout, VIRT_BASE_ADDRESS};
 
 static EXIT: AtomicPtr<u32> = AtomicPtr::new(null_mut());
 static UART: AtomicPtr<u8> = AtomicPtr::new(null_mut());
@@ -53,5 +53,23 @@ impl core::fmt::Write for Writer {
 }
 
 pub unsafe fn fw_cfg() -> FwCfg {
-    FwCfg::new_memory_mapped(FW_CFG.load(Ordering::Acquire)).unwrap()
+    FwCfg::take_memory_mapped(FW_CFG.load(Ordering::Acquire)).unwrap()
+}
+
+pub fn take_fw_cfg() -> Result<FwCfg, Error> {
+    // The device tree must agree with the default base address
+    assert_eq!(FW_CFG.load(Ordering::Acquire) as usize, VIRT_BASE_ADDRESS);
+    unsafe { FwCfg::take_virt() }
+}
+
+pub unsafe fn fw_cfg_with_quirks(quirks: FwCfgQuirks) -> Result<FwCfg, Error> {
+    FwCfgBuilder::new()
+        .quirks(quirks)
+        .take_memory_mapped(FW_CFG.load(Ordering::Acquire))
+}
+
+pub unsafe fn fw_cfg_with_layout(layout: MmioLayout) -> Result<FwCfg, Error> {
+    FwCfgBuilder::new()
+        .layout(layout)
+        .take_memory_mapped(FW_CFG.load(Ordering::Acquire))
 }